@@ -0,0 +1,77 @@
+//Must match MAX_CASCADES in src/renderer/shadow_map/cascaded_shadows.rs
+#define MAX_CASCADES 4
+
+uniform sampler2DArray cascade_shadow_map;
+//From view space into the clip space of every cascade
+uniform mat4 cascade_matrices[MAX_CASCADES];
+//View space distance at which every cascade ends
+uniform vec4 cascade_splits;
+uniform int cascade_count;
+uniform float cascade_blend;
+uniform float cascade_bias;
+uniform int cascade_pcf_radius;
+uniform bool cascade_debug;
+
+int select_cascade(vec3 position) {
+    for (int i = 0; i < cascade_count; i++) {
+        if (position.z < cascade_splits[i]) {
+            return i;
+        }
+    }
+    return -1;
+}
+
+float sample_cascade(int cascade, vec3 position) {
+    vec4 light_clip = cascade_matrices[cascade] * vec4(position, 1.0);
+    vec3 coords = light_clip.xyz / light_clip.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+
+    float current = coords.z - cascade_bias;
+    vec2 texel = 1.0 / vec2(textureSize(cascade_shadow_map, 0).xy);
+    float lit = 0.0;
+    for (int x = -cascade_pcf_radius; x <= cascade_pcf_radius; x++) {
+        for (int y = -cascade_pcf_radius; y <= cascade_pcf_radius; y++) {
+            float closest = texture(cascade_shadow_map, vec3(coords.xy + vec2(x, y) * texel, float(cascade))).r;
+            lit += current > closest ? 0.0 : 1.0;
+        }
+    }
+    float kernel_size = float(2 * cascade_pcf_radius + 1);
+    return lit / (kernel_size * kernel_size);
+}
+
+//How much of the shadow casting directional light reaches a view space position, 1 when fully lit
+float cascade_shadow(vec3 position) {
+    int cascade = select_cascade(position);
+    if (cascade < 0) {
+        return 1.0;
+    }
+
+    float shadow = sample_cascade(cascade, position);
+    float start = cascade == 0 ? 0.0 : cascade_splits[cascade - 1];
+    float end = cascade_splits[cascade];
+    float blend_start = end - (end - start) * cascade_blend;
+    if (cascade + 1 < cascade_count && position.z > blend_start) {
+        shadow = mix(shadow, sample_cascade(cascade + 1, position), (position.z - blend_start) / (end - blend_start));
+    }
+    return shadow;
+}
+
+//Colours every cascade differently while the debug view is on, white otherwise
+vec3 cascade_debug_tint(vec3 position) {
+    if (!cascade_debug) {
+        return vec3(1.0);
+    }
+    int cascade = select_cascade(position);
+    if (cascade == 0) {
+        return vec3(1.0, 0.4, 0.4);
+    } else if (cascade == 1) {
+        return vec3(0.4, 1.0, 0.4);
+    } else if (cascade == 2) {
+        return vec3(0.4, 0.4, 1.0);
+    } else if (cascade == 3) {
+        return vec3(1.0, 1.0, 0.4);
+    }
+    return vec3(1.0);
+}
@@ -0,0 +1,13 @@
+//Builds a tangent space from screen space derivatives, so meshes don't need to provide tangents
+mat3 cotangent_frame(vec3 normal, vec3 pos, vec2 uv) {
+    vec3 dp1 = dFdx(pos); //x differential on pos
+    vec3 dp2 = dFdy(pos); //y differential on pos
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+    float invmax = inversesqrt(max(dot(T, T), dot(B, B)));
+    return mat3(T * invmax, B * invmax, normal);
+}
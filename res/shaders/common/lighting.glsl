@@ -0,0 +1,62 @@
+//Must match MAX_LIGHTS in src/renderer/lighting.rs
+#define MAX_LIGHTS 16
+
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+#include "common/point_shadows.glsl"
+#include "common/cascaded_shadows.glsl"
+
+struct Light {
+    vec4 color;
+    vec4 position;
+    vec4 direction;
+    vec4 attenuation;
+    vec4 cone;
+};
+
+//Everything is in view space
+layout(std140) uniform Lights {
+    Light lights[MAX_LIGHTS];
+    int light_count;
+};
+
+//Direction towards the light, scaled by how much of it reaches the position
+vec3 light_direction(Light light, vec3 position, out float strength) {
+    int kind = int(light.position.w);
+    //A shadow slot on a directional light means it casts the cascaded shadows
+    int shadow_slot = int(light.cone.z);
+    if (kind == LIGHT_DIRECTIONAL) {
+        strength = shadow_slot >= 0 ? cascade_shadow(position) : 1.0;
+        return normalize(-light.direction.xyz);
+    }
+
+    vec3 to_light = light.position.xyz - position;
+    float distance = length(to_light);
+    vec3 direction = to_light / distance;
+    strength = 1.0 / (light.attenuation.x + light.attenuation.y * distance + light.attenuation.z * distance * distance);
+    if (kind == LIGHT_SPOT) {
+        float cos_angle = dot(-direction, normalize(light.direction.xyz));
+        strength *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+    }
+    if (shadow_slot >= 0) {
+        strength *= point_shadow(shadow_slot, position - light.position.xyz, light.direction.w, light.cone.w, light.attenuation.w);
+    }
+    return direction;
+}
+
+//Blinn-Phong summed over every light in the scene, without ambient
+vec3 shade_lights(vec3 position, vec3 normal, vec3 diffuse_color, vec3 specular_color, float shininess) {
+    vec3 camera_dir = normalize(-position);
+    vec3 result = vec3(0.0);
+    for (int i = 0; i < light_count; i++) {
+        float strength;
+        vec3 to_light = light_direction(lights[i], position, strength);
+        float diffuse = max(dot(normal, to_light), 0.0);
+        vec3 half_direction = normalize(to_light + camera_dir);
+        float specular = diffuse > 0.0 ? pow(max(dot(half_direction, normal), 0.0), shininess) : 0.0;
+        result += lights[i].color.rgb * strength * (diffuse * diffuse_color + specular * specular_color);
+    }
+    return result;
+}
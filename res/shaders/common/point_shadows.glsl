@@ -0,0 +1,46 @@
+//Must match MAX_POINT_SHADOWS in src/renderer/shadow_map/point_shadows.rs
+#define MAX_POINT_SHADOWS 4
+
+uniform samplerCube point_shadow_map_0;
+uniform samplerCube point_shadow_map_1;
+uniform samplerCube point_shadow_map_2;
+uniform samplerCube point_shadow_map_3;
+//Cube maps are laid out along the world axes, while lighting happens in view space
+uniform mat4 view_to_world;
+
+const int PCF_SAMPLES = 20;
+const vec3 pcf_offsets[PCF_SAMPLES] = vec3[](
+    vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+    vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+    vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+    vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+    vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+);
+
+//Samplers can only be indexed with constants in GLSL 1.50
+float sample_point_shadow(int slot, vec3 direction) {
+    if (slot == 0) {
+        return texture(point_shadow_map_0, direction).r;
+    } else if (slot == 1) {
+        return texture(point_shadow_map_1, direction).r;
+    } else if (slot == 2) {
+        return texture(point_shadow_map_2, direction).r;
+    }
+    return texture(point_shadow_map_3, direction).r;
+}
+
+//How much of a point light reaches a fragment, 1 when fully lit and 0 when fully in shadow.
+//Bias and PCF radius are in world units.
+float point_shadow(int slot, vec3 light_to_fragment, float far, float bias, float pcf_radius) {
+    vec3 direction = mat3(view_to_world) * light_to_fragment;
+    float current = (length(light_to_fragment) - bias) / far;
+    if (pcf_radius <= 0.0) {
+        return current > sample_point_shadow(slot, direction) ? 0.0 : 1.0;
+    }
+
+    float lit = 0.0;
+    for (int i = 0; i < PCF_SAMPLES; i++) {
+        lit += current > sample_point_shadow(slot, direction + pcf_offsets[i] * pcf_radius) ? 0.0 : 1.0;
+    }
+    return lit / float(PCF_SAMPLES);
+}
@@ -0,0 +1,8 @@
+uniform mat4 perspective;
+uniform mat4 view;
+uniform mat4 model;
+
+//Transforms normals with the inverse transpose, so non-uniform scaling does not skew them
+mat3 normal_matrix(mat4 modelview) {
+    return transpose(inverse(mat3(modelview)));
+}
@@ -0,0 +1,7 @@
+#version 150
+
+out float depth;
+
+void main() {
+    depth = gl_FragCoord.z;
+}
@@ -0,0 +1,18 @@
+#version 150
+
+in vec3 v_normal;
+in vec3 v_position;
+
+out vec4 color;
+
+#include "common/lighting.glsl"
+
+uniform vec4 base_color;
+
+const vec3 specular_color = vec3(0.2, 0.2, 0.2);
+
+void main() {
+    vec3 diffuse_color = base_color.rgb;
+    vec3 lit_color = diffuse_color * 0.1 + shade_lights(v_position, v_normal, diffuse_color, specular_color, 16.0);
+    color = vec4(lit_color * cascade_debug_tint(v_position), 1.0);
+}
@@ -0,0 +1,13 @@
+#version 150
+
+in vec3 position;
+
+out vec3 g_position;
+
+#include "common/transforms.glsl"
+
+void main() {
+    vec4 view_position = view * model * vec4(position, 1.0);
+    g_position = view_position.xyz;
+    gl_Position = perspective * view_position;
+}
@@ -4,14 +4,19 @@ in vec3 position;
 in vec3 normal;
 in vec2 tex_coords;
 
-out vec3 v_normal;
+out vec3 v_color;
 
-uniform mat4 perspective;
-uniform mat4 view;
-uniform mat4 model;
+#include "common/transforms.glsl"
+#include "common/lighting.glsl"
+
+const vec3 base_color = vec3(0.9, 0.9, 0.9);
 
 void main() {
     mat4 modelview = view * model;
-    v_normal = transpose(inverse(mat3(modelview))) * normal;
-    gl_Position = perspective * modelview * vec4(position, 1.0);
-}
\ No newline at end of file
+    vec4 view_position = modelview * vec4(position, 1.0);
+    vec3 view_normal = normalize(normal_matrix(modelview) * normal);
+    //Lit per vertex, the fragment shader only interpolates
+    v_color = base_color * 0.1 + shade_lights(view_position.xyz, view_normal, base_color, vec3(0.0), 1.0);
+    v_color *= cascade_debug_tint(view_position.xyz);
+    gl_Position = perspective * view_position;
+}
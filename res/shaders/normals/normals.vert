@@ -5,9 +5,7 @@ in vec3 normal;
 
 out vec3 v_normal;
 
-uniform mat4 perspective;
-uniform mat4 view;
-uniform mat4 model;
+#include "common/transforms.glsl"
 
 void main() {
     mat4 modelview = view * model;
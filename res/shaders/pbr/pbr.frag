@@ -0,0 +1,130 @@
+#version 150
+
+in vec3 v_normal;
+in vec3 v_position;
+#ifdef HAS_UV
+in vec2 v_tex_coords;
+#endif
+
+out vec4 color;
+
+#include "common/lighting.glsl"
+
+//Scalar values are used as they are without a map, and multiply the map's value when there is one
+uniform vec4 albedo;
+uniform float metallic;
+uniform float roughness;
+uniform float occlusion;
+uniform vec3 emissive;
+
+#if defined(HAS_UV) && defined(HAS_ALBEDO_MAP)
+uniform sampler2D albedo_map;
+#endif
+#if defined(HAS_UV) && defined(HAS_METALLIC_MAP)
+uniform sampler2D metallic_map;
+#endif
+#if defined(HAS_UV) && defined(HAS_ROUGHNESS_MAP)
+uniform sampler2D roughness_map;
+#endif
+#if defined(HAS_UV) && defined(HAS_OCCLUSION_MAP)
+uniform sampler2D occlusion_map;
+#endif
+#if defined(HAS_UV) && defined(HAS_EMISSIVE_MAP)
+uniform sampler2D emissive_map;
+#endif
+#if defined(HAS_UV) && defined(HAS_NORMAL_MAP)
+uniform sampler2D normal_map;
+
+#include "common/cotangent_frame.glsl"
+#endif
+
+const float PI = 3.14159265359;
+//Light that reaches the surface from everywhere else, there is no image based lighting yet
+const float AMBIENT = 0.03;
+
+//Trowbridge-Reitz GGX normal distribution
+float distribution_ggx(vec3 normal, vec3 half_direction, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float n_dot_h = max(dot(normal, half_direction), 0.0);
+    float denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denominator * denominator);
+}
+
+float geometry_schlick_ggx(float n_dot_v, float roughness) {
+    float r = roughness + 1.0;
+    float k = (r * r) / 8.0;
+    return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+//Smith's method, combining the shadowing towards the light and the masking towards the camera
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+void main() {
+    vec3 base_color = albedo.rgb;
+    float metalness = metallic;
+    float perceptual_roughness = roughness;
+    float ambient_occlusion = occlusion;
+    vec3 emission = emissive;
+#if defined(HAS_UV) && defined(HAS_ALBEDO_MAP)
+    base_color *= texture(albedo_map, v_tex_coords).rgb;
+#endif
+#if defined(HAS_UV) && defined(HAS_METALLIC_MAP)
+    metalness *= texture(metallic_map, v_tex_coords).r;
+#endif
+#if defined(HAS_UV) && defined(HAS_ROUGHNESS_MAP)
+    perceptual_roughness *= texture(roughness_map, v_tex_coords).r;
+#endif
+#if defined(HAS_UV) && defined(HAS_OCCLUSION_MAP)
+    ambient_occlusion *= texture(occlusion_map, v_tex_coords).r;
+#endif
+#if defined(HAS_UV) && defined(HAS_EMISSIVE_MAP)
+    emission *= texture(emissive_map, v_tex_coords).rgb;
+#endif
+    //Fully smooth surfaces turn the highlight into a single point
+    perceptual_roughness = clamp(perceptual_roughness, 0.04, 1.0);
+
+#if defined(HAS_UV) && defined(HAS_NORMAL_MAP)
+    vec3 normal_sample = texture(normal_map, v_tex_coords).rgb;
+    mat3 tbn = cotangent_frame(v_normal, v_position, v_tex_coords);
+    vec3 normal = normalize(tbn * -(normal_sample * 2.0 - 1.0));
+#else
+    vec3 normal = normalize(v_normal);
+#endif
+
+    vec3 camera_dir = normalize(-v_position);
+    float n_dot_v = max(dot(normal, camera_dir), 0.0001);
+    //Dielectrics reflect about 4% head on, metals tint their reflection with their albedo
+    vec3 f0 = mix(vec3(0.04), base_color, metalness);
+
+    vec3 radiance_out = vec3(0.0);
+    for (int i = 0; i < light_count; i++) {
+        float strength;
+        vec3 to_light = light_direction(lights[i], v_position, strength);
+        float n_dot_l = max(dot(normal, to_light), 0.0);
+        if (n_dot_l <= 0.0) {
+            continue;
+        }
+        vec3 half_direction = normalize(to_light + camera_dir);
+
+        //Cook-Torrance
+        float d = distribution_ggx(normal, half_direction, perceptual_roughness);
+        float g = geometry_smith(n_dot_v, n_dot_l, perceptual_roughness);
+        vec3 f = fresnel_schlick(max(dot(half_direction, camera_dir), 0.0), f0);
+        vec3 specular = d * g * f / (4.0 * n_dot_v * n_dot_l + 0.0001);
+
+        //Whatever is not reflected is refracted, metals absorb all of it
+        vec3 diffuse = (vec3(1.0) - f) * (1.0 - metalness) * base_color / PI;
+        radiance_out += (diffuse + specular) * lights[i].color.rgb * strength * n_dot_l;
+    }
+
+    vec3 ambient = AMBIENT * base_color * ambient_occlusion;
+    vec3 lit_color = ambient + radiance_out + emission;
+    color = vec4(lit_color * cascade_debug_tint(v_position), albedo.a);
+}
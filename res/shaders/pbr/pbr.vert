@@ -0,0 +1,26 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+#ifdef HAS_UV
+in vec2 tex_coords;
+#endif
+
+out vec3 v_normal;
+out vec3 v_position;
+#ifdef HAS_UV
+out vec2 v_tex_coords;
+#endif
+
+#include "common/transforms.glsl"
+
+void main() {
+#ifdef HAS_UV
+    v_tex_coords = tex_coords;
+#endif
+    mat4 modelview = view * model;
+    v_normal = normal_matrix(modelview) * normal;
+    vec4 view_position = modelview * vec4(position, 1.0);
+    v_position = view_position.xyz;
+    gl_Position = perspective * view_position;
+}
\ No newline at end of file
@@ -2,20 +2,25 @@
 
 in vec3 position;
 in vec3 normal;
+#ifdef HAS_UV
 in vec2 tex_coords;
+#endif
 
 out vec3 v_normal;
 out vec3 v_position;
+#ifdef HAS_UV
 out vec2 v_tex_coords;
+#endif
 
-uniform mat4 perspective;
-uniform mat4 view;
-uniform mat4 model;
+#include "common/transforms.glsl"
 
 void main() {
+#ifdef HAS_UV
     v_tex_coords = tex_coords;
+#endif
     mat4 modelview = view * model;
-    v_normal = transpose(inverse(mat3(modelview))) * normal;
-    gl_Position = perspective * modelview * vec4(position, 1.0);
-    v_position = gl_Position.xyz / gl_Position.w;
+    v_normal = normal_matrix(modelview) * normal;
+    vec4 view_position = modelview * vec4(position, 1.0);
+    v_position = view_position.xyz;
+    gl_Position = perspective * view_position;
 }
\ No newline at end of file
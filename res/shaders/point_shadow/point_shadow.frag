@@ -0,0 +1,13 @@
+#version 150
+
+in vec3 v_world_position;
+
+out float distance;
+
+uniform vec3 light_position;
+uniform float far;
+
+//Linear distance to the light, scaled so the far plane ends up at 1
+void main() {
+    distance = length(v_world_position - light_position) / far;
+}
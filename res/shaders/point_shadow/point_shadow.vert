@@ -0,0 +1,14 @@
+#version 150
+
+in vec3 position;
+
+out vec3 v_world_position;
+
+uniform mat4 model;
+uniform mat4 light_view_projection;
+
+void main() {
+    vec4 world_position = model * vec4(position, 1.0);
+    v_world_position = world_position.xyz;
+    gl_Position = light_view_projection * world_position;
+}
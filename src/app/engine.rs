@@ -0,0 +1,214 @@
+use super::{FrameTimer, Input, Report};
+use glium::{glutin, Surface};
+
+type InitCallback<'a> = Box<dyn FnMut(&mut Engine) + 'a>;
+type UpdateCallback<'a> = Box<dyn FnMut(&mut Engine, f32) + 'a>;
+type RenderCallback<'a> = Box<dyn FnMut(&mut Engine, &mut glium::Frame) + 'a>;
+type EventCallback<'a> = Box<dyn FnMut(&mut Engine, &glutin::Event) + 'a>;
+type ReportCallback<'a> = Box<dyn FnMut(&mut Engine, &Report) + 'a>;
+
+//The user side of the main loop, every callback is optional.
+//Callbacks are closures so they can hold on to whatever was loaded before Engine::run was called.
+pub struct App<'a> {
+    init: Option<InitCallback<'a>>,
+    update: Option<UpdateCallback<'a>>,
+    render: Option<RenderCallback<'a>>,
+    on_event: Option<EventCallback<'a>>,
+    on_report: Option<ReportCallback<'a>>,
+}
+
+impl<'a> Default for App<'a> {
+    fn default() -> App<'a> {
+        App::new()
+    }
+}
+
+impl<'a> App<'a> {
+    pub fn new() -> App<'a> {
+        App {
+            init: None,
+            update: None,
+            render: None,
+            on_event: None,
+            on_report: None,
+        }
+    }
+
+    //Called once, right before the first frame
+    pub fn with_init<F: FnMut(&mut Engine) + 'a>(mut self, init: F) -> App<'a> {
+        self.init = Some(Box::new(init));
+        self
+    }
+
+    //Called every frame with the time since the previous frame in seconds, after events were processed
+    pub fn with_update<F: FnMut(&mut Engine, f32) + 'a>(mut self, update: F) -> App<'a> {
+        self.update = Some(Box::new(update));
+        self
+    }
+
+    //Called every frame with a target that has already been cleared
+    pub fn with_render<F: FnMut(&mut Engine, &mut glium::Frame) + 'a>(
+        mut self,
+        render: F,
+    ) -> App<'a> {
+        self.render = Some(Box::new(render));
+        self
+    }
+
+    //Called for every glutin event, after the engine has updated its input state with it
+    pub fn with_event<F: FnMut(&mut Engine, &glutin::Event) + 'a>(
+        mut self,
+        on_event: F,
+    ) -> App<'a> {
+        self.on_event = Some(Box::new(on_event));
+        self
+    }
+
+    //Called at the end of every frame for everything reported during it, in order.
+    //Without it reports are written to stderr.
+    pub fn with_report<F: FnMut(&mut Engine, &Report) + 'a>(mut self, on_report: F) -> App<'a> {
+        self.on_report = Some(Box::new(on_report));
+        self
+    }
+}
+
+pub struct Engine {
+    display: glium::Display,
+    events_loop: glutin::EventsLoop,
+    draw_parameters: glium::DrawParameters<'static>,
+    clear_color: (f32, f32, f32, f32),
+    clear_depth: f32,
+    input: Input,
+    timer: FrameTimer,
+    reports: Vec<Report>,
+    closed: bool,
+}
+
+impl Engine {
+    pub fn new(title: &str) -> Engine {
+        let events_loop = glutin::EventsLoop::new();
+        let window = glutin::WindowBuilder::new().with_title(title);
+        let context = glutin::ContextBuilder::new()
+            .with_depth_buffer(24)
+            .with_multisampling(8);
+        let display = glium::Display::new(window, context, &events_loop).unwrap();
+
+        let draw_parameters = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+
+                ..Default::default()
+            },
+            backface_culling: glium::draw_parameters::BackfaceCullingMode::CullCounterClockwise,
+            ..Default::default()
+        };
+
+        Engine {
+            display,
+            events_loop,
+            draw_parameters,
+            clear_color: (0.01, 0.01, 0.01, 1.0),
+            clear_depth: 1.0,
+            input: Input::new(),
+            timer: FrameTimer::new(),
+            reports: Vec::new(),
+            closed: false,
+        }
+    }
+
+    pub fn get_display(&self) -> &glium::Display {
+        &self.display
+    }
+
+    pub fn get_draw_parameters(&self) -> &glium::DrawParameters<'static> {
+        &self.draw_parameters
+    }
+
+    pub fn set_draw_parameters(&mut self, draw_parameters: glium::DrawParameters<'static>) {
+        self.draw_parameters = draw_parameters;
+    }
+
+    pub fn set_clear_color(&mut self, clear_color: (f32, f32, f32, f32)) {
+        self.clear_color = clear_color;
+    }
+
+    //Reversed-Z projections need the depth buffer cleared to 0 instead of 1
+    pub fn set_clear_depth(&mut self, clear_depth: f32) {
+        self.clear_depth = clear_depth;
+    }
+
+    pub fn get_input(&self) -> &Input {
+        &self.input
+    }
+
+    pub fn get_timer(&self) -> &FrameTimer {
+        &self.timer
+    }
+
+    //Queues a report for App::with_report, it is handed over at the end of the frame
+    pub fn report_event(&mut self, message: String) {
+        self.reports.push(Report::Event(message));
+    }
+
+    pub fn report_error(&mut self, message: String) {
+        self.reports.push(Report::Error(message));
+    }
+
+    fn flush_reports(&mut self, app: &mut App) {
+        let reports: Vec<Report> = self.reports.drain(..).collect();
+        for report in reports.iter() {
+            match app.on_report.as_mut() {
+                Some(on_report) => on_report(self, report),
+                None => eprintln!("{}", report),
+            }
+        }
+    }
+
+    //Makes run return once the current frame is done
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+
+    //Runs the main loop until the window is closed or close is called
+    pub fn run(&mut self, mut app: App) {
+        if let Some(init) = app.init.as_mut() {
+            init(self);
+        }
+        self.flush_reports(&mut app);
+
+        while !self.closed {
+            self.timer.tick();
+
+            self.input.begin_frame();
+            let mut events = Vec::new();
+            self.events_loop.poll_events(|ev| events.push(ev));
+            for event in events.iter() {
+                if let glutin::Event::WindowEvent {
+                    event: glutin::WindowEvent::CloseRequested,
+                    ..
+                } = event
+                {
+                    self.closed = true;
+                }
+                self.input.process_event(event);
+                if let Some(on_event) = app.on_event.as_mut() {
+                    on_event(self, event);
+                }
+            }
+
+            let delta_time = self.timer.get_delta_time();
+            if let Some(update) = app.update.as_mut() {
+                update(self, delta_time);
+            }
+
+            let mut target = self.display.draw();
+            target.clear_color_and_depth(self.clear_color, self.clear_depth);
+            if let Some(render) = app.render.as_mut() {
+                render(self, &mut target);
+            }
+            target.finish().unwrap();
+            self.flush_reports(&mut app);
+        }
+    }
+}
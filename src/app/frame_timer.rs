@@ -0,0 +1,57 @@
+use std::time::SystemTime;
+
+pub struct FrameTimer {
+    timer: SystemTime,
+    time_current: f32,
+    time_previous: f32,
+    delta_time: f32,
+    frames: u64,
+}
+
+impl Default for FrameTimer {
+    fn default() -> FrameTimer {
+        FrameTimer::new()
+    }
+}
+
+impl FrameTimer {
+    pub fn new() -> FrameTimer {
+        FrameTimer {
+            timer: SystemTime::now(),
+            time_current: 0.0,
+            time_previous: 0.0,
+            delta_time: 0.0,
+            frames: 0,
+        }
+    }
+
+    //Advances the timer by one frame, should be called once at the start of every frame
+    pub fn tick(&mut self) {
+        self.frames += 1;
+        self.time_previous = self.time_current;
+        self.time_current = self.get_time();
+        self.delta_time = self.time_current - self.time_previous;
+    }
+
+    //Seconds passed since the timer was created
+    pub fn get_time(&self) -> f32 {
+        match self.timer.elapsed() {
+            Ok(elapsed) => {
+                ((elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64) as f64
+                    / 1_000_000_000.0) as f32
+            }
+            Err(e) => {
+                println!("Error: {:?}", e);
+                0.0
+            }
+        }
+    }
+
+    pub fn get_delta_time(&self) -> f32 {
+        self.delta_time
+    }
+
+    pub fn get_frame_count(&self) -> u64 {
+        self.frames
+    }
+}
@@ -0,0 +1,111 @@
+use glium::glutin;
+use glutin::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode};
+use std::collections::HashSet;
+use straal::Vec2n;
+
+//Input state as of the current frame, gathered from the glutin events by the Engine
+pub struct Input {
+    keys_down: HashSet<VirtualKeyCode>,
+    mouse_buttons_down: HashSet<MouseButton>,
+    mouse_position: Vec2n,
+    mouse_delta: Vec2n,
+    scroll_delta: f32,
+}
+
+impl Default for Input {
+    fn default() -> Input {
+        Input::new()
+    }
+}
+
+impl Input {
+    pub fn new() -> Input {
+        Input {
+            keys_down: HashSet::new(),
+            mouse_buttons_down: HashSet::new(),
+            mouse_position: Vec2n::zero(),
+            mouse_delta: Vec2n::zero(),
+            scroll_delta: 0.0,
+        }
+    }
+
+    //Clears everything that only applies to a single frame
+    pub(crate) fn begin_frame(&mut self) {
+        self.mouse_delta = Vec2n::zero();
+        self.scroll_delta = 0.0;
+    }
+
+    pub(crate) fn process_event(&mut self, event: &glutin::Event) {
+        match event {
+            glutin::Event::WindowEvent { event, .. } => match event {
+                glutin::WindowEvent::MouseInput { state, button, .. } => match state {
+                    ElementState::Pressed => {
+                        self.mouse_buttons_down.insert(*button);
+                    }
+                    ElementState::Released => {
+                        self.mouse_buttons_down.remove(button);
+                    }
+                },
+                glutin::WindowEvent::CursorMoved { position, .. } => {
+                    self.mouse_position = Vec2n::new(position.x as f32, position.y as f32);
+                }
+                glutin::WindowEvent::MouseWheel { delta, .. } => match delta {
+                    MouseScrollDelta::LineDelta(_, y) => self.scroll_delta += y,
+                    MouseScrollDelta::PixelDelta(pos) => self.scroll_delta += pos.y as f32,
+                },
+                glutin::WindowEvent::KeyboardInput { input, .. } => {
+                    if let Some(keycode) = input.virtual_keycode {
+                        match input.state {
+                            ElementState::Pressed => {
+                                self.keys_down.insert(keycode);
+                            }
+                            ElementState::Released => {
+                                self.keys_down.remove(&keycode);
+                            }
+                        }
+                    }
+                }
+                _ => (), //Don't do anything for other window events
+            },
+            glutin::Event::DeviceEvent { event, .. } => match event {
+                glutin::DeviceEvent::MouseMotion { delta } => {
+                    self.mouse_delta += Vec2n::new(delta.0 as f32, delta.1 as f32);
+                }
+                _ => (),
+            },
+            _ => (), //Don't do anything for other events
+        }
+    }
+
+    pub fn is_key_down(&self, keycode: VirtualKeyCode) -> bool {
+        self.keys_down.contains(&keycode)
+    }
+
+    pub fn is_mouse_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons_down.contains(&button)
+    }
+
+    //Cursor position in logical pixels, relative to the top left of the window
+    pub fn get_mouse_position(&self) -> Vec2n {
+        self.mouse_position
+    }
+
+    //Raw mouse motion accumulated this frame
+    pub fn get_mouse_delta(&self) -> Vec2n {
+        self.mouse_delta
+    }
+
+    //Scroll accumulated this frame, in lines for most mice
+    pub fn get_scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    //-1, 0 or 1 depending on which of the two keys is held, both held cancel out
+    pub fn get_axis(&self, negative: VirtualKeyCode, positive: VirtualKeyCode) -> f32 {
+        match (self.is_key_down(negative), self.is_key_down(positive)) {
+            (true, false) => -1.0,
+            (false, true) => 1.0,
+            _ => 0.0,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub use self::engine::{App, Engine};
+pub use self::frame_timer::FrameTimer;
+pub use self::input::Input;
+pub use self::report::Report;
+
+pub mod engine;
+pub mod frame_timer;
+pub mod input;
+pub mod report;
@@ -0,0 +1,19 @@
+use core::fmt;
+
+//Something the engine or the app wants to tell the user about, handed to App::with_report at the end of the frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum Report {
+    //Things that happened as expected, like a shader being reloaded
+    Event(String),
+    //Things that went wrong without stopping the app, like a shader that failed to compile
+    Error(String),
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Report::Event(message) => write!(f, "{}", message),
+            Report::Error(message) => write!(f, "Error: {}", message),
+        }
+    }
+}
@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate glium;
+extern crate image;
+extern crate ron;
+extern crate serde;
+extern crate straal;
+extern crate vertexify;
+
+pub mod app;
+pub mod renderer;
+
+pub use app::{App, Engine, Input, Report};
@@ -2,260 +2,410 @@
 extern crate glium;
 extern crate straal;
 extern crate vertexify;
+extern crate wolfsbane;
 
-use std::time::{Duration, SystemTime};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 use glium::{glutin, Surface};
-use glutin::dpi::LogicalPosition;
-use glutin::ElementState;
-use glutin::MouseScrollDelta;
-use glutin::VirtualKeyCode;
-use straal::{Mat3n, Mat4n, Quatn, Vec2n, Vec3n, Vec4n};
+use straal::{Mat4n, Quatn, Vec2n, Vec3n, Vec4n};
 
-mod renderer;
-use renderer::SceneContainer;
+use wolfsbane::renderer::{
+    Attenuation, Camera, CameraMode, CascadedShadowMaps, CollisionMesh, DirectionalShadow, Light,
+    LightBuffer, LightKind, Material, MaterialShader, MeshRenderer, OrbitController, PointShadow,
+    PointShadowMaps, SceneContainer, SceneNode, Shader, ShaderRegistry, ShaderVariantCache,
+};
+use wolfsbane::{App, Engine};
 
 #[allow(dead_code)]
 fn main() {
-    run_glium();
+    run_demo();
 }
 
-fn run_glium() {
-    let mut events_loop = glutin::EventsLoop::new();
-    let window = glutin::WindowBuilder::new();
-    let context = glutin::ContextBuilder::new()
-        .with_depth_buffer(24)
-        .with_multisampling(8);
-    let display = glium::Display::new(window, context, &events_loop).unwrap();
-
-    let draw_parameters = glium::DrawParameters {
-        depth: glium::Depth {
-            test: glium::draw_parameters::DepthTest::IfLess,
-            write: true,
-
-            ..Default::default()
-        },
-        backface_culling: glium::draw_parameters::BackfaceCullingMode::CullCounterClockwise,
-        ..Default::default()
-    };
-
-    let mut lucy_model = vertexify::ObjModel::load_from_file("res/meshes/lucy.obj").unwrap();
-    let lucy = lucy_model.gen_glium_buffer(&display);
-
-    let mut quad_model = vertexify::ObjModel::load_from_file("res/meshes/quad.obj").unwrap();
-    let quad = quad_model.gen_glium_buffer(&display);
-
-    let program = renderer::Shader::load(&display, renderer::Shader::NORMALS).unwrap();
-
-    let timer = SystemTime::now();
-    let mut time_current = 0.0;
-    let mut time_previous = 0.0;
-    let mut delta_time = 0.0;
-
-    let mut transform = renderer::Transform::default();
-
-    let view_matrix = get_view_matrix(
-        &Vec3n::new(0.0, 0.0, 1.0),
-        &Vec3n::new(0.0, 0.0, -1.0),
-        &Vec3n::new(0.0, 1.0, 0.0),
-    );
-    let light_direction = Vec3n::new(0.5, -0.5, 1.0).normalized();
-
-    let mut frames = 0;
-
-    //let mut position_delta = Vec2::zero();
-    let mut up_pressed = false;
-    let mut down_pressed = false;
-    let mut left_pressed = false;
-    let mut right_pressed = false;
-
-    //Mouse related debugging things
-    let mut mouse_delta = Vec2n::zero();
-    let mut mouse_down = false;
-    let mut mouse_zoom = 2.0;
-    let mut mouse_zoom_changed = false;
-
-    transform.set_local_scale(Vec3n::all(mouse_zoom));
-    //transform.rotate_angle_axis(std::f32::consts::FRAC_PI_4, Vec3n::up());
-
-    let mut rotation_matrix = Mat3n::identity();
-
-    let mut world = SceneContainer::new();
-    let mut world_access = (*world).get_mut();
-
-    let mut closed = false;
-    while !closed {
-        frames += 1;
-        time_previous = time_current;
-        time_current = get_time(&timer);
-        delta_time = time_current - time_previous;
-
-        let dx = match (left_pressed, right_pressed) {
-            (true, false) => 1.0,
-            (false, true) => -1.0,
-            _ => 0.0,
-        };
-
-        let dy = match (up_pressed, down_pressed) {
-            (true, false) => 1.0,
-            (false, true) => -1.0,
-            _ => 0.0,
-        };
-
-        let position_delta = Vec2n::new(dx, dy);
+fn run_demo() {
+    let mut engine = Engine::new("Wolfsbane");
+
+    let world = SceneContainer::new();
+    let model_id = SceneNode::create_new(world.clone(), Some("bunny"), None);
+    (*world)
+        .borrow_mut()
+        .add_component(
+            model_id,
+            MeshRenderer {
+                mesh: "res/meshes/bunny.obj".to_string(),
+                shader: "pbr".to_string(),
+                material: Some("bunny".to_string()),
+            },
+        )
+        .unwrap();
+    let model_node = (*world).borrow().get_node(model_id).unwrap();
+
+    let ground_id = SceneNode::create_new(world.clone(), Some("ground"), None);
+    (*world)
+        .borrow_mut()
+        .add_component(
+            ground_id,
+            MeshRenderer {
+                mesh: "res/meshes/quad.obj".to_string(),
+                shader: "phong".to_string(),
+                material: Some("ground".to_string()),
+            },
+        )
+        .unwrap();
+    {
+        let ground_node = (*world).borrow().get_node(ground_id).unwrap();
+        let mut ground_node = (*ground_node).borrow_mut();
+        ground_node
+            .get_transform()
+            .set_local_position(Vec3n::new(0.0, -1.0, 0.0));
+        ground_node.get_transform().set_local_scale(Vec3n::all(4.0));
+    }
 
-        let delta_factor = 200.0;
+    //The smooth teapot's normals are ignored by the flat shader, it still renders faceted
+    let teapot_id = SceneNode::create_new(world.clone(), Some("teapot"), None);
+    (*world)
+        .borrow_mut()
+        .add_component(
+            teapot_id,
+            MeshRenderer {
+                mesh: "res/meshes/teapot_smooth.obj".to_string(),
+                shader: "flat".to_string(),
+                material: Some("teapot".to_string()),
+            },
+        )
+        .unwrap();
+    {
+        let teapot_node = (*world).borrow().get_node(teapot_id).unwrap();
+        let mut teapot_node = (*teapot_node).borrow_mut();
+        teapot_node
+            .get_transform()
+            .set_local_position(Vec3n::new(1.5, -1.0, 0.5));
+        teapot_node.get_transform().set_local_scale(Vec3n::all(0.2));
+    }
 
-        if position_delta != Vec2n::zero() {
-            transform.translate(Vec3n::new(
-                position_delta.x / delta_factor,
-                position_delta.y / delta_factor,
-                0.0,
-            ));
+    let sun_id = SceneNode::create_new(world.clone(), Some("sun"), None);
+    (*world)
+        .borrow_mut()
+        .add_component(
+            sun_id,
+            Light {
+                kind: LightKind::Directional,
+                color: Vec3n::new(1.0, 0.95, 0.9),
+                intensity: 0.8,
+            },
+        )
+        .unwrap();
+    (*(*world).borrow().get_node(sun_id).unwrap())
+        .borrow_mut()
+        .get_transform()
+        .set_forward(Vec3n::new(-0.5, -1.0, 0.5), Vec3n::up());
+    (*world)
+        .borrow_mut()
+        .add_component(sun_id, DirectionalShadow::default())
+        .unwrap();
+
+    let lamp_id = SceneNode::create_new(world.clone(), Some("lamp"), None);
+    (*world)
+        .borrow_mut()
+        .add_component(
+            lamp_id,
+            Light {
+                kind: LightKind::Point(Attenuation::from_range(5.0)),
+                color: Vec3n::new(0.4, 0.6, 1.0),
+                intensity: 1.0,
+            },
+        )
+        .unwrap();
+    (*world)
+        .borrow_mut()
+        .add_component(lamp_id, PointShadow::default())
+        .unwrap();
+    (*(*world).borrow().get_node(lamp_id).unwrap())
+        .borrow_mut()
+        .get_transform()
+        .set_local_position(Vec3n::new(1.0, 1.5, -1.0));
+
+    let mut materials = HashMap::new();
+    let mut bunny_material = Material::pbr();
+    bunny_material.set_color("albedo", Vec4n::new(1.0, 0.71, 0.29, 1.0));
+    bunny_material.set_float("metallic", 1.0);
+    bunny_material.set_float("roughness", 0.35);
+    materials.insert("bunny".to_string(), bunny_material);
+    let mut ground_material =
+        Material::new(MaterialShader::Dynamic("phong".to_string(), Vec::new()));
+    ground_material.set_color("base_color", Vec4n::new(0.5, 0.5, 0.5, 1.0));
+    materials.insert("ground".to_string(), ground_material);
+    let mut teapot_material = Material::new(MaterialShader::Builtin(Shader::FLAT, Vec::new()));
+    teapot_material.set_color("base_color", Vec4n::new(0.3, 0.6, 0.9, 1.0));
+    materials.insert("teapot".to_string(), teapot_material);
+    //Shared between updating, which recompiles variants of edited dynamic shaders, and rendering
+    let variants = RefCell::new(ShaderVariantCache::new());
+
+    //Every mesh and shader referenced by the scene is loaded once up front,
+    //the container gets a collision mesh from the same model so right clicking can pick every drawn node
+    let mut meshes = HashMap::new();
+    let mut shaders = ShaderRegistry::new();
+    let mesh_renderers: Vec<_> = (*world)
+        .borrow()
+        .query::<MeshRenderer>()
+        .into_iter()
+        .map(|(_, mesh_renderer)| mesh_renderer.clone())
+        .collect();
+    for mesh_renderer in mesh_renderers {
+        if !meshes.contains_key(&mesh_renderer.mesh) {
+            let mut model = vertexify::ObjModel::load_from_file(&mesh_renderer.mesh).unwrap();
+            (*world).borrow_mut().set_collision_mesh(
+                &mesh_renderer.mesh,
+                Rc::new(CollisionMesh::from_obj_model(&model)),
+            );
+            meshes.insert(
+                mesh_renderer.mesh.clone(),
+                model.gen_glium_buffer(engine.get_display()),
+            );
         }
-
-        let mut target = display.draw();
-
-        let perspective_matrix = get_perspective_matrix(&Vec2n::from(target.get_dimensions()));
-
-        if mouse_zoom_changed {
-            transform.set_local_scale(Vec3n::all(mouse_zoom))
+        //Materials compile their own shader variants when first drawn
+        if mesh_renderer.material.is_none() && !shaders.contains(&mesh_renderer.shader) {
+            shaders
+                .load(engine.get_display(), &mesh_renderer.shader)
+                .unwrap();
         }
+    }
+    debug_assert!((*world).borrow().get_ids_without_collider().is_empty());
+    //Shared between updating, which reloads edited shaders, and rendering
+    let shaders = RefCell::new(shaders);
+
+    let camera_id = SceneNode::create_new(world.clone(), Some("camera"), None);
+    (*world)
+        .borrow_mut()
+        .add_component(
+            camera_id,
+            Camera::new(CameraMode::OrbCamera(OrbitController::new(
+                Vec3n::zero(),
+                2.0,
+            ))),
+        )
+        .unwrap();
+    //Materials whose program failed to compile, so the error is only reported once
+    let mut failed_materials = HashSet::new();
+    //Shader and material pairs whose inputs were checked, cleared when a shader is reloaded so it is checked again
+    let validated = RefCell::new(HashSet::new());
+    let mut lights = LightBuffer::new(engine.get_display()).unwrap();
+    let mut point_shadows = PointShadowMaps::new(engine.get_display()).unwrap();
+    //Shared between the event handler, which toggles the cascade debug view, and rendering
+    let cascaded_shadows = RefCell::new(CascadedShadowMaps::new(engine.get_display()).unwrap());
+
+    (*model_node)
+        .borrow_mut()
+        .get_transform()
+        .set_local_scale(Vec3n::all(2.0));
+
+    let app = App::new()
+        .with_update(|engine, delta_time| {
+            let mut shaders = shaders.borrow_mut();
+            let mut variants = variants.borrow_mut();
+            let reloaded = shaders
+                .reload_changed(engine.get_display())
+                .into_iter()
+                .chain(variants.reload_changed(engine.get_display()));
+            for (name, result) in reloaded {
+                validated.borrow_mut().clear();
+                match result {
+                    Ok(()) => engine.report_event(format!("Reloaded shader {}", name)),
+                    Err(err) => engine.report_error(format!(
+                        "Keeping the previous version of {}: {}",
+                        name, err
+                    )),
+                }
+            }
 
-        let mut rot = transform.get_local_rotation();
-        rot *= Quatn::get_quat_from_angle_axis(delta_time, Vec3n::up());
-        rot *= Quatn::get_quat_from_angle_axis(delta_time, Vec3n::right());
-        transform.set_local_rotation(rot);
-
-        let uniforms = uniform! {model : transform.get_local_to_world_matrix(), view: view_matrix, perspective : perspective_matrix, light_dir : light_direction};
-
-        target.clear_color_and_depth((0.01, 0.01, 0.01, 1.0), 1.0);
-
-        lucy.draw(&mut target, &program, &uniforms, &draw_parameters);
-        //lucy.draw(&mut target, &program, &uniforms_2, &draw_parameters);
-        //quad.draw(&mut target, &program, &uniforms, &draw_parameters);
+            if let Some(camera) = (*world)
+                .borrow_mut()
+                .get_component_mut::<Camera>(camera_id)
+            {
+                camera.update(engine.get_input(), delta_time);
+            }
 
-        target.finish().unwrap();
+            let mut model_node = (*model_node).borrow_mut();
+            let transform = model_node.get_transform();
+            let mut rot = transform.get_local_rotation();
+            rot *= Quatn::get_quat_from_angle_axis(delta_time, Vec3n::up());
+            rot *= Quatn::get_quat_from_angle_axis(delta_time, Vec3n::right());
+            transform.set_local_rotation(rot);
+        })
+        .with_event(|engine, event| {
+            //C toggles the view that tints every shadow cascade
+            if let glutin::Event::WindowEvent {
+                event:
+                    glutin::WindowEvent::KeyboardInput {
+                        input:
+                            glutin::KeyboardInput {
+                                state: glutin::ElementState::Pressed,
+                                virtual_keycode: Some(glutin::VirtualKeyCode::C),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } = event
+            {
+                let mut cascaded_shadows = cascaded_shadows.borrow_mut();
+                let debug_view = !cascaded_shadows.is_debug_view();
+                cascaded_shadows.set_debug_view(debug_view);
+            }
 
-        mouse_delta = Vec2n::zero();
-        mouse_zoom_changed = false;
-        //Processing the glutin events
-        events_loop.poll_events(|ev| {
-            match ev {
-                glutin::Event::WindowEvent { event, .. } => match event {
-                    glutin::WindowEvent::CloseRequested => closed = true,
-                    glutin::WindowEvent::MouseInput { state, button, .. } => {
-                        if button == glutin::MouseButton::Left {
-                            mouse_down = match state {
-                                ElementState::Pressed => true,
-                                ElementState::Released => false,
-                            };
-                        }
-                    }
-                    glutin::WindowEvent::MouseWheel { delta, .. } => match delta {
-                        MouseScrollDelta::LineDelta(x, y) => {
-                            mouse_zoom += y / 10.0;
-                            mouse_zoom_changed = true;
-                        }
-                        MouseScrollDelta::PixelDelta(pos) => {
-                            mouse_zoom += pos.y as f32;
-                            mouse_zoom_changed = true;
-                        }
+            //Right clicking picks whatever is under the cursor
+            if let glutin::Event::WindowEvent {
+                event:
+                    glutin::WindowEvent::MouseInput {
+                        state: glutin::ElementState::Pressed,
+                        button: glutin::MouseButton::Right,
+                        ..
                     },
-                    glutin::WindowEvent::KeyboardInput { input, .. } => {
-                        match input.virtual_keycode {
-                            Some(keycode) => match keycode {
-                                VirtualKeyCode::W => {
-                                    up_pressed = input.state == ElementState::Pressed
-                                }
-                                VirtualKeyCode::S => {
-                                    down_pressed = input.state == ElementState::Pressed
-                                }
-                                VirtualKeyCode::A => {
-                                    left_pressed = input.state == ElementState::Pressed
-                                }
-                                VirtualKeyCode::D => {
-                                    right_pressed = input.state == ElementState::Pressed
+                ..
+            } = event
+            {
+                let size = match engine.get_display().gl_window().get_inner_size() {
+                    Some(size) => size,
+                    None => return,
+                };
+                let viewport = Vec2n::new(size.width as f32, size.height as f32);
+                let mut world = (*world).borrow_mut();
+                let ray = match world.get_component_mut::<Camera>(camera_id) {
+                    Some(camera) => camera
+                        .screen_point_to_ray(engine.get_input().get_mouse_position(), &viewport),
+                    None => return,
+                };
+                let picked = match world.raycast(&ray) {
+                    Some(hit) => format!(
+                        "Picked {} at triangle {}",
+                        (*world.get_node(hit.node).unwrap()).borrow().get_name(),
+                        hit.triangle
+                    ),
+                    None => "Nothing under the cursor".to_string(),
+                };
+                engine.report_event(picked);
+            }
+        })
+        .with_render(|engine, target| {
+            let viewport = Vec2n::from(target.get_dimensions());
+            let (projection, perspective_matrix, view_matrix) = {
+                let mut scene = (*world).borrow_mut();
+                let camera = match scene.get_component_mut::<Camera>(camera_id) {
+                    Some(camera) => camera,
+                    None => return,
+                };
+                (
+                    camera.get_projection(),
+                    camera.get_projection_matrix(&viewport),
+                    camera.get_view_matrix(),
+                )
+            };
+            let shaders = shaders.borrow();
+            let mut variants = variants.borrow_mut();
+            let mut validated = validated.borrow_mut();
+            let world = (*world).borrow();
+            lights.update(&world, &view_matrix);
+            point_shadows.render(engine.get_display(), &world, |surface, pass| {
+                for_each_mesh_renderer(&world, |_, mesh_renderer, model_matrix| {
+                    meshes[&mesh_renderer.mesh].draw(
+                        &mut *surface,
+                        pass.get_program(),
+                        &pass.get_uniforms(model_matrix),
+                        pass.get_draw_parameters(),
+                    );
+                });
+            });
+            let mut cascaded_shadows = cascaded_shadows.borrow_mut();
+            cascaded_shadows.render(
+                engine.get_display(),
+                &world,
+                &projection,
+                &view_matrix,
+                &viewport,
+                |surface, pass| {
+                    for_each_mesh_renderer(&world, |_, mesh_renderer, model_matrix| {
+                        meshes[&mesh_renderer.mesh].draw(
+                            &mut *surface,
+                            pass.get_program(),
+                            &pass.get_uniforms(model_matrix),
+                            pass.get_draw_parameters(),
+                        );
+                    });
+                },
+            );
+            for_each_mesh_renderer(&world, |node, mesh_renderer, model_matrix| {
+                let uniforms = uniform! {model : model_matrix, view: view_matrix, perspective : perspective_matrix, Lights : lights.get_buffer()};
+                let uniforms = point_shadows.get_uniforms(&view_matrix, &uniforms);
+                let uniforms = cascaded_shadows.get_uniforms(&view_matrix, &uniforms);
+                let mesh = &meshes[&mesh_renderer.mesh];
+                match mesh_renderer.material.as_ref().and_then(|name| materials.get(name)) {
+                    Some(material) => {
+                        //A material that does not compile is reported once and left out until it does
+                        let program = match material
+                            .get_program(engine.get_display(), &mut variants)
+                        {
+                            Ok(program) => {
+                                failed_materials.remove(&mesh_renderer.material);
+                                program
+                            }
+                            Err(err) => {
+                                if failed_materials.insert(mesh_renderer.material.clone()) {
+                                    engine.report_error(format!(
+                                        "Skipping {}: {}",
+                                        node.get_name(),
+                                        err
+                                    ));
                                 }
-                                _ => {}
-                            },
-                            None => {}
+                                return;
+                            }
+                        };
+                        if validated.insert((
+                            mesh_renderer.shader.clone(),
+                            mesh_renderer.material.clone(),
+                        )) {
+                            for err in material.validate(program, &uniforms) {
+                                engine.report_error(format!("{}: {}", node.get_name(), err));
+                            }
                         }
+                        mesh.draw(
+                            &mut *target,
+                            program,
+                            &material.get_uniforms(&uniforms),
+                            &material.get_draw_parameters(engine.get_draw_parameters()),
+                        );
                     }
-                    _ => (), //Don't do anything for other window events
-                },
-                glutin::Event::DeviceEvent { event, .. } => match event {
-                    glutin::DeviceEvent::MouseMotion { delta } => {
-                        mouse_delta = Vec2n::from((delta.0 as f32, delta.1 as f32));
+                    None => {
+                        let program = shaders.get(&mesh_renderer.shader).unwrap();
+                        if validated.insert((mesh_renderer.shader.clone(), None)) {
+                            let reflection = Shader::reflect(program);
+                            for err in reflection.validate_uniforms(&uniforms) {
+                                engine.report_error(format!("{}: {}", node.get_name(), err));
+                            }
+                        }
+                        mesh.draw(
+                            &mut *target,
+                            program,
+                            &uniforms,
+                            engine.get_draw_parameters(),
+                        );
                     }
-                    _ => (),
-                },
-                _ => (), //Don't do anything for other events
-            }
+                }
+            });
         });
-    }
-}
-
-pub fn get_perspective_matrix(target_dims: &Vec2n) -> Mat4n {
-    let aspect_ratio = target_dims.y as f32 / target_dims.x as f32;
-    let fov = std::f32::consts::PI / 3.0;
-    let z_far = 1024.0;
-    let z_near = 0.1;
-    let f = 1.0 / (fov / 2.0).tan();
 
-    Mat4n::new(
-        f * aspect_ratio,
-        0.0,
-        0.0,
-        0.0,
-        0.0,
-        f,
-        0.0,
-        0.0,
-        0.0,
-        0.0,
-        (z_far + z_near) / (z_far - z_near),
-        -(2.0 * z_far * z_near) / (z_far - z_near),
-        0.0,
-        0.0,
-        1.0,
-        0.0,
-    )
+    engine.run(app);
 }
 
-pub fn get_view_matrix(pos: &Vec3n, dir: &Vec3n, up: &Vec3n) -> Mat4n {
-    let fwd = dir.normalized();
-    let rht = up.cross(fwd).normalized();
-    let up = fwd.cross(rht);
-    let pos = Vec3n {
-        x: -pos.dot(rht),
-        y: -pos.dot(up),
-        z: -pos.dot(fwd),
-    };
-
-    Mat4n::new_from_vec4s(
-        Vec4n::from((rht, pos.x)),
-        Vec4n::from((up, pos.y)),
-        Vec4n::from((fwd, pos.z)),
-        Vec4n::new(0.0, 0.0, 0.0, 1.0),
-    )
-}
-
-pub fn get_model_matrix(pos: &Vec3n, scale: f32) -> Mat4n {
-    Mat4n::new(
-        scale, 0.0, 0.0, pos.x, 0.0, scale, 0.0, pos.y, 0.0, 0.0, scale, pos.z, 0.0, 0.0, 0.0, 1.0,
-    )
-}
-
-pub fn get_time(timer: &SystemTime) -> f32 {
-    match timer.elapsed() {
-        Ok(elapsed) => {
-            ((elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64) as f64
-                / 1_000_000_000.0) as f32
-        }
-        Err(e) => {
-            println!("Error: {:?}", e);
-            0.0
+//Calls back with every node that has a MeshRenderer and its model matrix, every pass draws the scene through this
+fn for_each_mesh_renderer<F>(world: &SceneContainer, mut visit: F)
+where
+    F: FnMut(&mut SceneNode, &MeshRenderer, Mat4n),
+{
+    world.visit_with_world(|node_id, _, node, parent_world| {
+        if let Some(mesh_renderer) = world.get_component::<MeshRenderer>(node_id) {
+            let model_matrix = *parent_world * node.get_transform().get_local_matrix();
+            visit(node, mesh_renderer, model_matrix);
         }
-    }
+    });
 }
@@ -1,44 +1,237 @@
-use straal::{FloatType, Mat4, Vec3};
+use straal::{Mat4n, Vec2n, Vec3n, Vec4n};
 
+use crate::app::Input;
+
+use super::camera_controllers::{FirstPersonController, LookAtController, OrbitController};
+use super::projection::Projection;
+use super::ray::Ray;
 use super::*;
 
 pub enum CameraMode {
-    FirstPerson,
-    LookAt,
-    OrbCamera,
+    FirstPerson(FirstPersonController),
+    LookAt(LookAtController),
+    OrbCamera(OrbitController),
 }
 
-pub struct Camera<S> {
-    transform: Transform<S>,
-    view_matrix: Mat4<S>,
-    changed: bool,
+//Attached to a scene node as a component, the render loop looks it up there.
+//The camera moves through its own transform, which its controller drives, the node's transform does not move it.
+pub struct Camera {
+    transform: Transform<f32>,
+    view_matrix: Mat4n,
+    view_revision: Option<u64>,
     mode: CameraMode,
+    projection: Projection,
+    reversed_z: bool,
+    projection_matrix: Mat4n,
+    projection_aspect: Option<f32>,
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::new(CameraMode::FirstPerson(FirstPersonController::default()))
+    }
 }
 
-impl<S> Camera<S> where S: FloatType<S> {
-    fn default() -> Camera<S> {
+impl Camera {
+    pub fn new(mode: CameraMode) -> Camera {
         Camera {
             transform: Transform::default(),
-            view_matrix: Mat4::identity(),
-            changed: true,
-            mode: CameraMode::FirstPerson,
+            view_matrix: Mat4n::identity(),
+            view_revision: None,
+            mode,
+            projection: Projection::default(),
+            reversed_z: false,
+            projection_matrix: Mat4n::identity(),
+            projection_aspect: None,
         }
     }
 
+    //The view matrix is only rebuilt when the transform changed since it was last calculated
     fn update_view_matrix(&mut self) {
         self.view_matrix = self.transform.get_local_to_world_matrix().inverse();
-        self.changed = false;
+        self.view_revision = Some(self.transform.get_revision());
     }
 
-    pub fn get_view_matrix(&mut self) -> Mat4<S> {
-        if self.changed {
+    pub fn get_view_matrix(&mut self) -> Mat4n {
+        if self.view_revision != Some(self.transform.get_revision()) {
             self.update_view_matrix();
         }
         self.view_matrix
     }
 
-    pub fn look_at(&mut self, dir: Vec3<S>) {
-        self.transform.set_forward(dir, Vec3::forward());
-        self.changed = true;
+    pub fn get_transform(&mut self) -> &mut Transform<f32> {
+        &mut self.transform
+    }
+
+    pub fn get_mode(&mut self) -> &mut CameraMode {
+        &mut self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: CameraMode) {
+        self.mode = mode;
+    }
+
+    pub fn look_at(&mut self, dir: Vec3n) {
+        self.transform.set_forward(dir, Vec3n::up());
+    }
+
+    pub fn get_projection(&self) -> Projection {
+        self.projection
+    }
+
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+        self.projection_aspect = None;
+    }
+
+    pub fn is_reversed_z(&self) -> bool {
+        self.reversed_z
+    }
+
+    //Draw parameters and the depth clear have to follow along, see Projection::get_depth_test and Projection::get_clear_depth
+    //This only flips the depth direction, it does not improve depth precision, see Projection::get_matrix for why
+    pub fn set_reversed_z(&mut self, reversed_z: bool) {
+        self.reversed_z = reversed_z;
+        self.projection_aspect = None;
+    }
+
+    //The projection is only rebuilt when the settings or the aspect ratio of the viewport changed
+    pub fn get_projection_matrix(&mut self, viewport: &Vec2n) -> Mat4n {
+        let aspect_ratio = viewport.x / viewport.y;
+        if self.projection_aspect != Some(aspect_ratio) {
+            self.projection_matrix = self.projection.get_matrix(aspect_ratio, self.reversed_z);
+            self.projection_aspect = Some(aspect_ratio);
+        }
+        self.projection_matrix
+    }
+
+    pub fn get_view_projection_matrix(&mut self, viewport: &Vec2n) -> Mat4n {
+        self.get_projection_matrix(viewport) * self.get_view_matrix()
     }
-}
\ No newline at end of file
+
+    //Pixels start at the top left of the viewport like glutin's mouse position,
+    //depth is in the same [0, 1] range the depth buffer stores
+    pub fn unproject(&mut self, pixel: Vec2n, depth: f32, viewport: &Vec2n) -> Vec3n {
+        let ndc = Vec4n::new(
+            2.0 * pixel.x / viewport.x - 1.0,
+            1.0 - 2.0 * pixel.y / viewport.y,
+            2.0 * depth - 1.0,
+            1.0,
+        );
+        let world = self.get_view_projection_matrix(viewport).inverse() * ndc;
+        Vec3n::new(world.x / world.w, world.y / world.w, world.z / world.w)
+    }
+
+    //Gives the pixel position in x and y and the depth buffer value in z,
+    //None when the point is behind a perspective camera
+    pub fn world_to_screen(&mut self, point: Vec3n, viewport: &Vec2n) -> Option<Vec3n> {
+        let clip = self.get_view_projection_matrix(viewport) * Vec4n::from((point, 1.0));
+        if clip.w <= 0.0 {
+            return None;
+        }
+        Some(Vec3n::new(
+            (clip.x / clip.w + 1.0) / 2.0 * viewport.x,
+            (1.0 - clip.y / clip.w) / 2.0 * viewport.y,
+            (clip.z / clip.w + 1.0) / 2.0,
+        ))
+    }
+
+    //The ray starts on the near plane, the second point is picked closer than the far plane so infinite projections work too
+    pub fn screen_point_to_ray(&mut self, pixel: Vec2n, viewport: &Vec2n) -> Ray {
+        let (near_depth, inner_depth) = if self.reversed_z {
+            (1.0, 0.75)
+        } else {
+            (0.0, 0.5)
+        };
+        let origin = self.unproject(pixel, near_depth, viewport);
+        let inner = self.unproject(pixel, inner_depth, viewport);
+        Ray::new(origin, inner - origin)
+    }
+
+    //Lets the active controller move the camera, call once per frame after input has been gathered
+    pub fn update(&mut self, input: &Input, delta_time: f32) {
+        match &mut self.mode {
+            CameraMode::FirstPerson(controller) => {
+                controller.update(&mut self.transform, input, delta_time)
+            }
+            CameraMode::LookAt(controller) => {
+                controller.update(&mut self.transform, input, delta_time)
+            }
+            CameraMode::OrbCamera(controller) => {
+                controller.update(&mut self.transform, input, delta_time)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use straal::Quatn;
+
+    fn get_camera(projection: Projection, reversed_z: bool) -> Camera {
+        let mut camera = Camera::default();
+        camera.set_projection(projection);
+        camera.set_reversed_z(reversed_z);
+        let transform = camera.get_transform();
+        transform.set_local_position(Vec3n::new(1.0, 2.0, -5.0));
+        transform.set_local_rotation(Quatn::get_quat_from_angle_axis(0.3, Vec3n::up()));
+        camera
+    }
+
+    //Projects the point onto the screen and checks that the ray through that pixel passes through the point again
+    fn assert_round_trip(camera: &mut Camera, point: Vec3n) {
+        let viewport = Vec2n::new(800.0, 600.0);
+        let screen = camera.world_to_screen(point, &viewport).unwrap();
+        assert!(
+            screen.z > 0.0 && screen.z < 1.0,
+            "{:?} is outside of the depth range",
+            screen
+        );
+
+        let ray = camera.screen_point_to_ray(Vec2n::new(screen.x, screen.y), &viewport);
+        let distance = (point - ray.origin).dot(ray.direction);
+        assert!(distance > 0.0, "{:?} is behind {:?}", point, ray);
+        let miss = (ray.get_point(distance) - point).length();
+        assert!(miss < 2e-3, "{:?} misses {:?} by {}", ray, point, miss);
+    }
+
+    #[test]
+    fn screen_rays_pass_through_projected_points() {
+        let points = [
+            Vec3n::new(1.0, 2.0, 0.0),
+            Vec3n::new(2.5, 1.0, 3.0),
+            Vec3n::new(-1.0, 3.5, 10.0),
+        ];
+        let projections = [
+            Projection::default(),
+            Projection::Perspective {
+                fov: 1.2,
+                near: 0.1,
+                far: None,
+            },
+            Projection::Orthographic {
+                size: 4.0,
+                near: 0.1,
+                far: 50.0,
+            },
+        ];
+        for projection in projections.iter() {
+            for reversed_z in [false, true].iter() {
+                let mut camera = get_camera(*projection, *reversed_z);
+                for point in points.iter() {
+                    assert_round_trip(&mut camera, *point);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn points_behind_a_perspective_camera_are_not_on_screen() {
+        let mut camera = get_camera(Projection::default(), false);
+        let viewport = Vec2n::new(800.0, 600.0);
+        assert!(camera
+            .world_to_screen(Vec3n::new(1.0, 2.0, -10.0), &viewport)
+            .is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use glium::glutin::{MouseButton, VirtualKeyCode};
+use straal::{Quatn, Vec3n};
+
+use crate::app::Input;
+
+use super::Transform;
+
+//Mouse movement is in pixels, this turns it into radians
+const DEFAULT_LOOK_SENSITIVITY: f32 = 0.005;
+//Just short of straight up/down, so the view never flips over the pole
+const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+fn get_yaw_pitch_rotation(yaw: f32, pitch: f32) -> Quatn {
+    //Rotating forward around the right axis by a positive angle tilts it down, so pitch is negated to make positive look up
+    Quatn::get_quat_from_angle_axis(yaw, Vec3n::up())
+        * Quatn::get_quat_from_angle_axis(-pitch, Vec3n::right())
+}
+
+fn get_look_delta(input: &Input, look_button: Option<MouseButton>, sensitivity: f32) -> (f32, f32) {
+    if let Some(button) = look_button {
+        if !input.is_mouse_down(button) {
+            return (0.0, 0.0);
+        }
+    }
+    let mouse_delta = input.get_mouse_delta();
+    //Screen space y grows downwards, moving the mouse up should look up
+    (mouse_delta.x * sensitivity, -mouse_delta.y * sensitivity)
+}
+
+//Fly camera, mouse looks around and WASD/QE moves relative to where the camera is facing
+pub struct FirstPersonController {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub move_speed: f32,
+    pub look_sensitivity: f32,
+    //Only look around while this button is held, None always follows the mouse
+    pub look_button: Option<MouseButton>,
+}
+
+impl FirstPersonController {
+    pub fn new(yaw: f32, pitch: f32) -> FirstPersonController {
+        FirstPersonController {
+            yaw,
+            pitch: pitch.max(-PITCH_LIMIT).min(PITCH_LIMIT),
+            move_speed: 2.0,
+            look_sensitivity: DEFAULT_LOOK_SENSITIVITY,
+            look_button: Some(MouseButton::Right),
+        }
+    }
+
+    pub fn update(&mut self, transform: &mut Transform<f32>, input: &Input, delta_time: f32) {
+        let (yaw_delta, pitch_delta) =
+            get_look_delta(input, self.look_button, self.look_sensitivity);
+        self.yaw += yaw_delta;
+        self.pitch = (self.pitch + pitch_delta)
+            .max(-PITCH_LIMIT)
+            .min(PITCH_LIMIT);
+        transform.set_local_rotation(get_yaw_pitch_rotation(self.yaw, self.pitch));
+
+        let movement = transform.get_right() * input.get_axis(VirtualKeyCode::A, VirtualKeyCode::D)
+            + Vec3n::up() * input.get_axis(VirtualKeyCode::Q, VirtualKeyCode::E)
+            + transform.get_forward() * input.get_axis(VirtualKeyCode::S, VirtualKeyCode::W);
+        if movement != Vec3n::zero() {
+            transform.translate(movement.normalized() * (self.move_speed * delta_time));
+        }
+    }
+}
+
+impl Default for FirstPersonController {
+    fn default() -> FirstPersonController {
+        FirstPersonController::new(0.0, 0.0)
+    }
+}
+
+//Keeps the camera pointed at a target, WASD/QE moves the camera around it and scrolling moves closer or further away
+pub struct LookAtController {
+    pub target: Vec3n,
+    pub move_speed: f32,
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+}
+
+impl LookAtController {
+    pub fn new(target: Vec3n) -> LookAtController {
+        LookAtController {
+            target,
+            move_speed: 2.0,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+        }
+    }
+
+    pub fn update(&mut self, transform: &mut Transform<f32>, input: &Input, delta_time: f32) {
+        let movement = transform.get_right() * input.get_axis(VirtualKeyCode::A, VirtualKeyCode::D)
+            + transform.get_up() * input.get_axis(VirtualKeyCode::Q, VirtualKeyCode::E)
+            + transform.get_forward() * input.get_axis(VirtualKeyCode::S, VirtualKeyCode::W);
+        if movement != Vec3n::zero() {
+            transform.translate(movement.normalized() * (self.move_speed * delta_time));
+        }
+
+        let to_target = self.target - transform.get_local_position();
+        let distance = to_target.length();
+        if distance <= 0.0 {
+            return;
+        }
+
+        let scroll = input.get_scroll_delta();
+        if scroll != 0.0 {
+            let new_distance = (distance * (1.0 - scroll * self.zoom_speed)).max(self.min_distance);
+            transform.set_local_position(self.target - to_target * (new_distance / distance));
+        }
+
+        transform.set_forward(to_target, Vec3n::up());
+    }
+}
+
+//Circles a pivot point, dragging the mouse changes yaw and pitch and scrolling changes the distance
+pub struct OrbitController {
+    pub pivot: Vec3n,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    pub look_sensitivity: f32,
+    pub zoom_speed: f32,
+    //Only orbit while this button is held, None always follows the mouse
+    pub look_button: Option<MouseButton>,
+}
+
+impl OrbitController {
+    pub fn new(pivot: Vec3n, distance: f32) -> OrbitController {
+        OrbitController {
+            pivot,
+            yaw: 0.0,
+            pitch: 0.0,
+            distance,
+            min_distance: 0.1,
+            max_distance: 1000.0,
+            look_sensitivity: DEFAULT_LOOK_SENSITIVITY,
+            zoom_speed: 0.1,
+            look_button: Some(MouseButton::Left),
+        }
+    }
+
+    pub fn update(&mut self, transform: &mut Transform<f32>, input: &Input, _delta_time: f32) {
+        let (yaw_delta, pitch_delta) =
+            get_look_delta(input, self.look_button, self.look_sensitivity);
+        self.yaw += yaw_delta;
+        self.pitch = (self.pitch + pitch_delta)
+            .max(-PITCH_LIMIT)
+            .min(PITCH_LIMIT);
+        self.distance = (self.distance * (1.0 - input.get_scroll_delta() * self.zoom_speed))
+            .max(self.min_distance)
+            .min(self.max_distance);
+
+        let rotation = get_yaw_pitch_rotation(self.yaw, self.pitch);
+        transform.set_local_rotation(rotation);
+        transform.set_local_position(self.pivot - (rotation * Vec3n::forward()) * self.distance);
+    }
+}
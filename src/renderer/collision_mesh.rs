@@ -0,0 +1,357 @@
+use straal::{Mat4n, Vec3n, Vec4n};
+use vertexify::ObjModel;
+
+use super::ray::Ray;
+
+//Ray directions are normalized, so below this a ray counts as running along a box's slab
+const SLAB_EPSILON: f32 = 1e-7;
+
+//Below this sine of the angle between a ray and a triangle's plane the ray counts as parallel to the triangle.
+//Relative to the triangle's edges and the ray's direction, so it holds for meshes and directions of any scale.
+const PARALLEL_EPSILON: f32 = 1e-6;
+
+fn transform_point(matrix: &Mat4n, point: Vec3n) -> Vec3n {
+    let p = *matrix * Vec4n::from((point, 1.0));
+    Vec3n::new(p.x / p.w, p.y / p.w, p.z / p.w)
+}
+
+fn transform_vector(matrix: &Mat4n, vector: Vec3n) -> Vec3n {
+    let v = *matrix * Vec4n::from((vector, 0.0));
+    Vec3n::new(v.x, v.y, v.z)
+}
+
+//Axis aligned bounding box
+#[derive(Clone, Copy, Debug)]
+pub struct Aabb {
+    pub min: Vec3n,
+    pub max: Vec3n,
+}
+
+impl Aabb {
+    pub fn from_points(points: &[Vec3n]) -> Aabb {
+        let mut min = Vec3n::all(std::f32::INFINITY);
+        let mut max = Vec3n::all(std::f32::NEG_INFINITY);
+        for point in points.iter() {
+            min = Vec3n::new(min.x.min(point.x), min.y.min(point.y), min.z.min(point.z));
+            max = Vec3n::new(max.x.max(point.x), max.y.max(point.y), max.z.max(point.z));
+        }
+        Aabb { min, max }
+    }
+
+    //The box around all 8 transformed corners, so it stays axis aligned in the new space
+    pub fn transformed(&self, matrix: &Mat4n) -> Aabb {
+        let mut corners = Vec::with_capacity(8);
+        for i in 0..8 {
+            corners.push(transform_point(
+                matrix,
+                Vec3n::new(
+                    if i & 1 == 0 { self.min.x } else { self.max.x },
+                    if i & 2 == 0 { self.min.y } else { self.max.y },
+                    if i & 4 == 0 { self.min.z } else { self.max.z },
+                ),
+            ));
+        }
+        Aabb::from_points(&corners)
+    }
+
+    //Distance along the ray to where it enters the box, 0 when the origin is inside
+    pub fn intersect_ray(&self, ray: &Ray) -> Option<f32> {
+        let origin = [ray.origin.x, ray.origin.y, ray.origin.z];
+        let direction = [ray.direction.x, ray.direction.y, ray.direction.z];
+        let min = [self.min.x, self.min.y, self.min.z];
+        let max = [self.max.x, self.max.y, self.max.z];
+
+        let mut t_enter = 0.0f32;
+        let mut t_exit = std::f32::INFINITY;
+        for axis in 0..3 {
+            if direction[axis].abs() < SLAB_EPSILON {
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (min[axis] - origin[axis]) / direction[axis];
+            let t1 = (max[axis] - origin[axis]) / direction[axis];
+            t_enter = t_enter.max(t0.min(t1));
+            t_exit = t_exit.min(t0.max(t1));
+            if t_enter > t_exit {
+                return None;
+            }
+        }
+        Some(t_enter)
+    }
+}
+
+//Where a ray crossed a triangle, u and v weigh the triangle's second and third vertex
+#[derive(Clone, Copy, Debug)]
+pub struct TriangleHit {
+    pub triangle: usize,
+    pub distance: f32,
+    pub u: f32,
+    pub v: f32,
+}
+
+//Triangle data kept on the CPU for picking, in the mesh's local space
+pub struct CollisionMesh {
+    positions: Vec<Vec3n>,
+    triangles: Vec<[usize; 3]>,
+    bounds: Aabb,
+}
+
+impl CollisionMesh {
+    pub fn new(positions: Vec<Vec3n>, triangles: Vec<[usize; 3]>) -> CollisionMesh {
+        let bounds = Aabb::from_points(&positions);
+        CollisionMesh {
+            positions,
+            triangles,
+            bounds,
+        }
+    }
+
+    //Takes the triangle list vertexify builds for drawing, so triangle indices match the triangles on screen.
+    //Every three vertices form a triangle, like the buffer from gen_glium_buffer is drawn.
+    pub fn from_obj_model(model: &ObjModel) -> CollisionMesh {
+        let positions: Vec<Vec3n> = model
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let [x, y, z] = vertex.position;
+                Vec3n::new(x, y, z)
+            })
+            .collect();
+        let triangles = (0..positions.len() / 3)
+            .map(|i| [i * 3, i * 3 + 1, i * 3 + 2])
+            .collect();
+        CollisionMesh::new(positions, triangles)
+    }
+
+    pub fn get_bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    pub fn get_triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn get_triangle(&self, index: usize) -> Option<[Vec3n; 3]> {
+        let [a, b, c] = *self.triangles.get(index)?;
+        Some([self.positions[a], self.positions[b], self.positions[c]])
+    }
+
+    //Nearest triangle hit by a ray in the mesh's local space, both faces of a triangle count.
+    //The direction does not have to be normalized, distances are in multiples of it.
+    pub fn intersect_ray(&self, origin: Vec3n, direction: Vec3n) -> Option<TriangleHit> {
+        let mut nearest: Option<TriangleHit> = None;
+        for (index, [a, b, c]) in self.triangles.iter().enumerate() {
+            let (v0, v1, v2) = (self.positions[*a], self.positions[*b], self.positions[*c]);
+            //Möller-Trumbore
+            let edge1 = v1 - v0;
+            let edge2 = v2 - v0;
+            let p = direction.cross(edge2);
+            let determinant = edge1.dot(p);
+            let scale = edge1.length() * edge2.length() * direction.length();
+            if determinant.abs() <= PARALLEL_EPSILON * scale {
+                continue;
+            }
+            let inverse_determinant = 1.0 / determinant;
+            let to_origin = origin - v0;
+            let u = to_origin.dot(p) * inverse_determinant;
+            if !(0.0..=1.0).contains(&u) {
+                continue;
+            }
+            let q = to_origin.cross(edge1);
+            let v = direction.dot(q) * inverse_determinant;
+            if v < 0.0 || u + v > 1.0 {
+                continue;
+            }
+            let distance = edge2.dot(q) * inverse_determinant;
+            if distance < 0.0 || nearest.map_or(false, |hit| hit.distance <= distance) {
+                continue;
+            }
+            nearest = Some(TriangleHit {
+                triangle: index,
+                distance,
+                u,
+                v,
+            });
+        }
+        nearest
+    }
+
+    //Same as intersect_ray, but for a world space ray against the mesh placed with the given local to world matrix.
+    //The distance stays in world units since the world ray's direction is normalized.
+    pub fn intersect_world_ray(&self, ray: &Ray, local_to_world: &Mat4n) -> Option<TriangleHit> {
+        let world_to_local = local_to_world.inverse();
+        self.intersect_ray(
+            transform_point(&world_to_local, ray.origin),
+            transform_vector(&world_to_local, ray.direction),
+        )
+    }
+}
+
+pub(crate) fn transform_triangle(local_to_world: &Mat4n, triangle: [Vec3n; 3]) -> [Vec3n; 3] {
+    [
+        transform_point(local_to_world, triangle[0]),
+        transform_point(local_to_world, triangle[1]),
+        transform_point(local_to_world, triangle[2]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn get_unit_box() -> Aabb {
+        Aabb::from_points(&[Vec3n::all(-1.0), Vec3n::all(1.0)])
+    }
+
+    //A right triangle in the z = 0 plane with its right angle at the origin
+    fn get_triangle_at(z: f32) -> [Vec3n; 3] {
+        [
+            Vec3n::new(0.0, 0.0, z),
+            Vec3n::new(2.0, 0.0, z),
+            Vec3n::new(0.0, 2.0, z),
+        ]
+    }
+
+    fn get_mesh(triangles: &[[Vec3n; 3]]) -> CollisionMesh {
+        let positions = triangles.iter().flat_map(|t| t.iter().copied()).collect();
+        let indices = (0..triangles.len())
+            .map(|i| [i * 3, i * 3 + 1, i * 3 + 2])
+            .collect();
+        CollisionMesh::new(positions, indices)
+    }
+
+    #[test]
+    fn ray_enters_box_at_nearest_face() {
+        let ray = Ray::new(Vec3n::new(0.5, 0.0, 5.0), Vec3n::new(0.0, 0.0, -1.0));
+        let distance = get_unit_box().intersect_ray(&ray).unwrap();
+        assert!((distance - 4.0).abs() < EPSILON);
+
+        let diagonal = Ray::new(Vec3n::all(-3.0), Vec3n::all(1.0));
+        let distance = get_unit_box().intersect_ray(&diagonal).unwrap();
+        assert!((distance - 2.0 * 3.0f32.sqrt()).abs() < EPSILON);
+    }
+
+    #[test]
+    fn ray_inside_box_hits_at_its_origin() {
+        let ray = Ray::new(Vec3n::new(0.2, -0.3, 0.4), Vec3n::new(1.0, 2.0, 3.0));
+        assert_eq!(get_unit_box().intersect_ray(&ray), Some(0.0));
+    }
+
+    #[test]
+    fn ray_misses_box() {
+        let beside = Ray::new(Vec3n::new(2.0, 0.0, 5.0), Vec3n::new(0.0, 0.0, -1.0));
+        assert_eq!(get_unit_box().intersect_ray(&beside), None);
+
+        let skewed = Ray::new(Vec3n::new(0.0, 0.0, 5.0), Vec3n::new(1.0, 0.0, -1.0));
+        assert_eq!(get_unit_box().intersect_ray(&skewed), None);
+
+        let behind = Ray::new(Vec3n::new(0.0, 0.0, 5.0), Vec3n::new(0.0, 0.0, 1.0));
+        assert_eq!(get_unit_box().intersect_ray(&behind), None);
+    }
+
+    #[test]
+    fn ray_parallel_to_box_faces() {
+        let between = Ray::new(Vec3n::new(-5.0, 0.5, 0.5), Vec3n::new(1.0, 0.0, 0.0));
+        let distance = get_unit_box().intersect_ray(&between).unwrap();
+        assert!((distance - 4.0).abs() < EPSILON);
+
+        let outside = Ray::new(Vec3n::new(-5.0, 1.5, 0.5), Vec3n::new(1.0, 0.0, 0.0));
+        assert_eq!(get_unit_box().intersect_ray(&outside), None);
+    }
+
+    #[test]
+    fn ray_hits_triangle_with_distance_and_barycentrics() {
+        let mesh = get_mesh(&[get_triangle_at(0.0)]);
+        let hit = mesh
+            .intersect_ray(Vec3n::new(0.5, 1.0, 3.0), Vec3n::new(0.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!(hit.triangle, 0);
+        assert!((hit.distance - 3.0).abs() < EPSILON);
+        assert!((hit.u - 0.25).abs() < EPSILON);
+        assert!((hit.v - 0.5).abs() < EPSILON);
+
+        //Distances are in multiples of an unnormalized direction
+        let hit = mesh
+            .intersect_ray(Vec3n::new(0.5, 1.0, 3.0), Vec3n::new(0.0, 0.0, -2.0))
+            .unwrap();
+        assert!((hit.distance - 1.5).abs() < EPSILON);
+    }
+
+    #[test]
+    fn ray_hits_triangle_from_behind() {
+        let mesh = get_mesh(&[get_triangle_at(0.0)]);
+        let hit = mesh
+            .intersect_ray(Vec3n::new(0.5, 0.5, -2.0), Vec3n::new(0.0, 0.0, 1.0))
+            .unwrap();
+        assert!((hit.distance - 2.0).abs() < EPSILON);
+        assert!((hit.u - 0.25).abs() < EPSILON);
+        assert!((hit.v - 0.25).abs() < EPSILON);
+    }
+
+    #[test]
+    fn ray_misses_triangle() {
+        let mesh = get_mesh(&[get_triangle_at(0.0)]);
+        let down = Vec3n::new(0.0, 0.0, -1.0);
+        //Past the hypotenuse, and on the other side of each leg
+        assert!(mesh
+            .intersect_ray(Vec3n::new(1.5, 1.5, 3.0), down)
+            .is_none());
+        assert!(mesh
+            .intersect_ray(Vec3n::new(-0.5, 0.5, 3.0), down)
+            .is_none());
+        assert!(mesh
+            .intersect_ray(Vec3n::new(0.5, -0.5, 3.0), down)
+            .is_none());
+        //The triangle is behind the ray's origin
+        assert!(mesh
+            .intersect_ray(Vec3n::new(0.5, 0.5, -3.0), down)
+            .is_none());
+    }
+
+    #[test]
+    fn ray_parallel_to_triangle_misses_at_any_scale() {
+        let along = Vec3n::new(1.0, 0.0, 0.0);
+        for scale in [1e-3, 1.0, 1e3].iter() {
+            let triangle = get_triangle_at(0.0);
+            let scaled = [
+                triangle[0] * *scale,
+                triangle[1] * *scale,
+                triangle[2] * *scale,
+            ];
+            let mesh = get_mesh(&[scaled]);
+            let origin = Vec3n::new(-1.0, 0.5, 0.0) * *scale;
+            assert!(mesh.intersect_ray(origin, along).is_none());
+            assert!(mesh.intersect_ray(origin, along * 1e-3).is_none());
+        }
+    }
+
+    #[test]
+    fn small_triangles_still_get_hit() {
+        //Its determinant is well below 1e-7, an absolute cutoff would take the ray for parallel
+        let tiny = get_triangle_at(0.0);
+        let tiny = [tiny[0] * 1e-3, tiny[1] * 1e-3, tiny[2] * 1e-3];
+        let mesh = get_mesh(&[tiny]);
+        let hit = mesh
+            .intersect_ray(Vec3n::new(5e-4, 5e-4, 1.0), Vec3n::new(0.0, 0.0, -1e-2))
+            .unwrap();
+        assert!((hit.distance - 100.0).abs() < 1e-2);
+    }
+
+    #[test]
+    fn nearest_triangle_wins() {
+        let mesh = get_mesh(&[
+            get_triangle_at(-1.0),
+            get_triangle_at(1.0),
+            get_triangle_at(4.0),
+        ]);
+        let hit = mesh
+            .intersect_ray(Vec3n::new(0.5, 0.5, 3.0), Vec3n::new(0.0, 0.0, -1.0))
+            .unwrap();
+        assert_eq!(hit.triangle, 1);
+        assert!((hit.distance - 2.0).abs() < EPSILON);
+    }
+}
@@ -0,0 +1,347 @@
+use glium::buffer::BufferCreationError;
+use glium::uniforms::UniformBuffer;
+use straal::{Mat4n, Vec3n, Vec4n};
+
+use super::scene_map::components::{Light, LightKind, PointShadow};
+use super::scene_map::scene_container::SceneContainer;
+use super::shadow_map::cascaded_shadows::get_directional_shadow;
+use super::shadow_map::point_shadows::{get_point_shadow, MAX_POINT_SHADOWS};
+
+//Has to match MAX_LIGHTS in res/shaders/common/lighting.glsl
+pub const MAX_LIGHTS: usize = 16;
+
+const KIND_DIRECTIONAL: f32 = 0.0;
+const KIND_POINT: f32 = 1.0;
+const KIND_SPOT: f32 = 2.0;
+
+//Which shadow map a light reads its shadows from
+#[derive(Clone, Copy, Debug)]
+pub enum LightShadow {
+    //One of the PointShadowMaps cube maps
+    Point(usize, PointShadow),
+    //The cascades of CascadedShadowMaps
+    Cascaded,
+}
+
+//One entry of the light array, laid out as std140 so every member is a vec4
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct LightData {
+    //rgb premultiplied by intensity
+    color: [f32; 4],
+    //xyz in view space, w holds the kind of light
+    position: [f32; 4],
+    //Direction the light travels in view space, w holds the far plane of its shadow map
+    direction: [f32; 4],
+    //w holds the PCF radius of its shadow map
+    attenuation: [f32; 4],
+    //Cosines of the inner and outer angle of a spot light, then the shadow map slot (-1 for none) and the shadow bias
+    cone: [f32; 4],
+}
+
+implement_uniform_block!(LightData, color, position, direction, attenuation, cone);
+
+impl LightData {
+    const EMPTY: LightData = LightData {
+        color: [0.0; 4],
+        position: [0.0; 4],
+        direction: [0.0; 4],
+        attenuation: [1.0, 0.0, 0.0, 0.0],
+        cone: [0.0, 0.0, -1.0, 0.0],
+    };
+}
+
+//The contents of the Lights uniform block
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct LightBlock {
+    lights: [LightData; MAX_LIGHTS],
+    light_count: i32,
+}
+
+implement_uniform_block!(LightBlock, lights, light_count);
+
+impl Default for LightBlock {
+    fn default() -> LightBlock {
+        LightBlock::new()
+    }
+}
+
+impl LightBlock {
+    pub fn new() -> LightBlock {
+        LightBlock {
+            lights: [LightData::EMPTY; MAX_LIGHTS],
+            light_count: 0,
+        }
+    }
+
+    pub fn get_light_count(&self) -> usize {
+        self.light_count as usize
+    }
+
+    //Returns false once the block is full, the light is dropped in that case.
+    //The shadow tells the shaders which shadow map to read this light's shadows from.
+    pub fn push(
+        &mut self,
+        light: &Light,
+        view_position: Vec3n,
+        view_direction: Vec3n,
+        shadow: Option<LightShadow>,
+    ) -> bool {
+        if self.get_light_count() >= MAX_LIGHTS {
+            return false;
+        }
+        let (kind, attenuation, cone) = match light.kind {
+            LightKind::Directional => (KIND_DIRECTIONAL, [1.0, 0.0, 0.0, 0.0], [0.0; 4]),
+            LightKind::Point(a) => (
+                KIND_POINT,
+                [a.constant, a.linear, a.quadratic, 0.0],
+                [0.0; 4],
+            ),
+            LightKind::Spot {
+                attenuation: a,
+                inner_angle,
+                outer_angle,
+            } => (
+                KIND_SPOT,
+                [a.constant, a.linear, a.quadratic, 0.0],
+                [inner_angle.cos(), outer_angle.cos(), 0.0, 0.0],
+            ),
+        };
+        let (mut attenuation, mut cone) = (attenuation, cone);
+        let mut far = 0.0;
+        match shadow {
+            Some(LightShadow::Point(slot, shadow)) => {
+                far = shadow.far;
+                attenuation[3] = shadow.pcf_radius;
+                cone[2] = slot as f32;
+                cone[3] = shadow.bias;
+            }
+            Some(LightShadow::Cascaded) => cone[2] = 0.0,
+            None => cone[2] = -1.0,
+        }
+        let color = light.color * light.intensity;
+        self.lights[self.get_light_count()] = LightData {
+            color: [color.x, color.y, color.z, 1.0],
+            position: [view_position.x, view_position.y, view_position.z, kind],
+            direction: [view_direction.x, view_direction.y, view_direction.z, far],
+            attenuation,
+            cone,
+        };
+        self.light_count += 1;
+        true
+    }
+
+    //Collects every Light component in the scene, placed in the view space of the given view matrix
+    pub fn gather(container: &SceneContainer, view_matrix: &Mat4n) -> LightBlock {
+        let mut block = LightBlock::new();
+        let mut shadow_slots = 0;
+        let mut cascades_taken = false;
+        container.visit_with_world(|node_id, _, node, parent_world| {
+            let light = match container.get_component::<Light>(node_id) {
+                Some(light) => light,
+                None => return,
+            };
+            let model_view = *view_matrix * *parent_world * node.get_transform().get_local_matrix();
+            let position = model_view * Vec4n::new(0.0, 0.0, 0.0, 1.0);
+            let direction = model_view * Vec4n::from((Vec3n::forward(), 0.0));
+            let shadow = match get_point_shadow(container, node_id, light) {
+                Some(shadow) if shadow_slots < MAX_POINT_SHADOWS => {
+                    shadow_slots += 1;
+                    Some(LightShadow::Point(shadow_slots - 1, shadow))
+                }
+                _ if !cascades_taken
+                    && get_directional_shadow(container, node_id, light).is_some() =>
+                {
+                    cascades_taken = true;
+                    Some(LightShadow::Cascaded)
+                }
+                _ => None,
+            };
+            block.push(
+                light,
+                Vec3n::new(position.x, position.y, position.z),
+                Vec3n::new(direction.x, direction.y, direction.z).normalized(),
+                shadow,
+            );
+        });
+        block
+    }
+}
+
+//The GPU side of the scene's lights, bind it to the Lights block of the built-in shaders
+pub struct LightBuffer {
+    buffer: UniformBuffer<LightBlock>,
+}
+
+impl LightBuffer {
+    pub fn new(display: &glium::Display) -> Result<LightBuffer, BufferCreationError> {
+        Ok(LightBuffer {
+            buffer: UniformBuffer::new(display, LightBlock::new())?,
+        })
+    }
+
+    //Call once per frame after the camera moved, before drawing anything lit
+    pub fn update(&mut self, container: &SceneContainer, view_matrix: &Mat4n) {
+        self.buffer
+            .write(&LightBlock::gather(container, view_matrix));
+    }
+
+    pub fn get_buffer(&self) -> &UniformBuffer<LightBlock> {
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::scene_map::components::{Attenuation, DirectionalShadow};
+    use crate::renderer::scene_map::node_handle::NodeHandle;
+    use crate::renderer::scene_map::scene_node::SceneNode;
+    use crate::renderer::shadow_map::point_shadows::get_point_shadow_casters;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const ATTENUATION: Attenuation = Attenuation {
+        constant: 1.0,
+        linear: 0.5,
+        quadratic: 0.25,
+    };
+
+    fn get_light(kind: LightKind) -> Light {
+        Light {
+            kind,
+            color: Vec3n::new(1.0, 0.5, 0.25),
+            intensity: 2.0,
+        }
+    }
+
+    fn add_light(
+        container: &Rc<RefCell<SceneContainer>>,
+        parent: Option<NodeHandle>,
+        position: Vec3n,
+        kind: LightKind,
+    ) -> NodeHandle {
+        let node_id = SceneNode::create_new(container.clone(), None, parent);
+        let mut scene = container.borrow_mut();
+        let node = scene.get_node(node_id).unwrap();
+        (*node)
+            .borrow_mut()
+            .get_transform()
+            .set_local_position(position);
+        scene.add_component(node_id, get_light(kind)).unwrap();
+        node_id
+    }
+
+    #[test]
+    fn push_packs_every_light_kind() {
+        let mut block = LightBlock::new();
+        let position = Vec3n::new(1.0, 2.0, 3.0);
+        let direction = Vec3n::new(0.0, 0.0, -1.0);
+        let shadow = PointShadow {
+            far: 30.0,
+            bias: 0.1,
+            pcf_radius: 0.05,
+            ..PointShadow::default()
+        };
+        let spot = LightKind::Spot {
+            attenuation: ATTENUATION,
+            inner_angle: 0.25,
+            outer_angle: 0.5,
+        };
+        let lights = [
+            (LightKind::Directional, Some(LightShadow::Cascaded)),
+            (
+                LightKind::Point(ATTENUATION),
+                Some(LightShadow::Point(2, shadow)),
+            ),
+            (spot, None),
+        ];
+        for (kind, shadow) in lights.iter() {
+            assert!(block.push(&get_light(*kind), position, direction, *shadow));
+        }
+        assert_eq!(block.get_light_count(), 3);
+
+        let [directional, point, spot] = [block.lights[0], block.lights[1], block.lights[2]];
+        for light in [directional, point, spot].iter() {
+            assert_eq!(light.color, [2.0, 1.0, 0.5, 1.0]);
+            assert_eq!(light.position[..3], [1.0, 2.0, 3.0]);
+            assert_eq!(light.direction[..3], [0.0, 0.0, -1.0]);
+        }
+
+        assert_eq!(directional.position[3], KIND_DIRECTIONAL);
+        assert_eq!(directional.attenuation, [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(directional.cone, [0.0, 0.0, 0.0, 0.0]);
+
+        assert_eq!(point.position[3], KIND_POINT);
+        assert_eq!(point.direction[3], 30.0);
+        assert_eq!(point.attenuation, [1.0, 0.5, 0.25, 0.05]);
+        assert_eq!(point.cone, [0.0, 0.0, 2.0, 0.1]);
+
+        assert_eq!(spot.position[3], KIND_SPOT);
+        assert_eq!(spot.direction[3], 0.0);
+        assert_eq!(spot.attenuation, [1.0, 0.5, 0.25, 0.0]);
+        assert_eq!(spot.cone, [0.25f32.cos(), 0.5f32.cos(), -1.0, 0.0]);
+    }
+
+    #[test]
+    fn push_drops_lights_past_max_lights() {
+        let mut block = LightBlock::new();
+        let light = get_light(LightKind::Directional);
+        for _ in 0..MAX_LIGHTS {
+            assert!(block.push(&light, Vec3n::zero(), Vec3n::up(), None));
+        }
+        assert!(!block.push(&light, Vec3n::zero(), Vec3n::up(), None));
+        assert_eq!(block.get_light_count(), MAX_LIGHTS);
+    }
+
+    #[test]
+    fn gather_hands_out_shadow_slots_in_the_order_they_are_rendered() {
+        let container = SceneContainer::new();
+        let point = LightKind::Point(ATTENUATION);
+        //The second root is created before the first root's children, but comes after them in pre-order
+        let first = add_light(&container, None, Vec3n::new(1.0, 0.0, 0.0), point);
+        let second = add_light(&container, None, Vec3n::new(2.0, 0.0, 0.0), point);
+        let first_child = add_light(&container, Some(first), Vec3n::new(0.0, 1.0, 0.0), point);
+        let second_child = add_light(&container, Some(second), Vec3n::new(0.0, 2.0, 0.0), point);
+        let last_child = add_light(&container, Some(first), Vec3n::new(0.0, 3.0, 0.0), point);
+        add_light(&container, Some(first), Vec3n::new(0.0, 4.0, 0.0), point);
+        let sun = add_light(&container, None, Vec3n::zero(), LightKind::Directional);
+        let second_sun = add_light(&container, None, Vec3n::zero(), LightKind::Directional);
+        {
+            let mut scene = container.borrow_mut();
+            for node_id in [first, second, first_child, second_child, last_child].iter() {
+                scene
+                    .add_component(*node_id, PointShadow::default())
+                    .unwrap();
+            }
+            scene
+                .add_component(sun, DirectionalShadow::default())
+                .unwrap();
+            scene
+                .add_component(second_sun, DirectionalShadow::default())
+                .unwrap();
+        }
+
+        let scene = container.borrow();
+        let block = LightBlock::gather(&scene, &Mat4n::identity());
+        assert_eq!(block.get_light_count(), 8);
+        let lights = &block.lights[..block.get_light_count()];
+
+        //Pre-order is first, its three children, second, its child, then the two suns.
+        //The light without a PointShadow and the one past MAX_POINT_SHADOWS read no shadow map,
+        //only the first sun gets the cascades.
+        let slots: Vec<f32> = lights.iter().map(|light| light.cone[2]).collect();
+        assert_eq!(slots, vec![0.0, 1.0, 2.0, -1.0, 3.0, -1.0, 0.0, -1.0]);
+
+        let casters = get_point_shadow_casters(&scene);
+        assert_eq!(casters.len(), MAX_POINT_SHADOWS);
+        for (slot, (_, position, _)) in casters.iter().enumerate() {
+            let light = lights
+                .iter()
+                .find(|light| light.position[..3] == [position.x, position.y, position.z])
+                .unwrap();
+            assert_eq!(light.cone[2], slot as f32);
+        }
+    }
+}
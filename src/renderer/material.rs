@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use glium::draw_parameters::{BackfaceCullingMode, Blend, DepthTest, PolygonMode};
+use glium::uniforms::{AsUniformValue, SamplerBehavior, UniformValue, Uniforms};
+use straal::{Mat4n, Vec2n, Vec3n, Vec4n};
+
+use super::shader_helpers::{Shader, ShaderError};
+use super::shader_reflection::{ReflectionError, ShaderReflection};
+use super::shader_variants::{
+    ShaderVariantCache, HAS_ALBEDO_MAP, HAS_EMISSIVE_MAP, HAS_METALLIC_MAP, HAS_NORMAL_MAP,
+    HAS_OCCLUSION_MAP, HAS_ROUGHNESS_MAP, HAS_UV,
+};
+use super::texture_manager::{SamplerSettings, Texture};
+
+//The shader a material is drawn with, along with the feature keys of the variant it needs
+#[derive(Clone)]
+pub enum MaterialShader {
+    Builtin(Shader, Vec<String>),
+    Dynamic(String, Vec<String>),
+}
+
+#[derive(Clone)]
+pub enum MaterialParameter {
+    Float(f32),
+    Vec2(Vec2n),
+    Vec3(Vec3n),
+    Vec4(Vec4n),
+    //RGBA, in linear space
+    Color(Vec4n),
+    Mat4(Mat4n),
+    Texture(Rc<Texture>, Option<SamplerBehavior>),
+}
+
+impl MaterialParameter {
+    fn as_uniform_value(&self) -> UniformValue {
+        match self {
+            MaterialParameter::Float(value) => UniformValue::Float(*value),
+            MaterialParameter::Vec2(value) => value.as_uniform_value(),
+            MaterialParameter::Vec3(value) => value.as_uniform_value(),
+            MaterialParameter::Vec4(value) => value.as_uniform_value(),
+            MaterialParameter::Color(value) => value.as_uniform_value(),
+            MaterialParameter::Mat4(value) => value.as_uniform_value(),
+            MaterialParameter::Texture(texture, sampler) => texture.as_uniform_value(*sampler),
+        }
+    }
+}
+
+//Per material changes to the engine's DrawParameters, anything left at None keeps the engine's value
+#[derive(Clone, Default)]
+pub struct DrawOverrides {
+    pub depth_test: Option<DepthTest>,
+    pub depth_write: Option<bool>,
+    pub backface_culling: Option<BackfaceCullingMode>,
+    pub blend: Option<Blend>,
+    pub polygon_mode: Option<PolygonMode>,
+}
+
+//The texture slots of the PBR shader
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PbrMap {
+    Albedo,
+    Metallic,
+    Roughness,
+    Occlusion,
+    Normal,
+    Emissive,
+}
+
+impl PbrMap {
+    //The sampler it is bound to and the feature key that enables it
+    fn get_binding(self) -> (&'static str, &'static str) {
+        match self {
+            PbrMap::Albedo => ("albedo_map", HAS_ALBEDO_MAP),
+            PbrMap::Metallic => ("metallic_map", HAS_METALLIC_MAP),
+            PbrMap::Roughness => ("roughness_map", HAS_ROUGHNESS_MAP),
+            PbrMap::Occlusion => ("occlusion_map", HAS_OCCLUSION_MAP),
+            PbrMap::Normal => ("normal_map", HAS_NORMAL_MAP),
+            PbrMap::Emissive => ("emissive_map", HAS_EMISSIVE_MAP),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Material {
+    shader: MaterialShader,
+    parameters: HashMap<String, MaterialParameter>,
+    draw_overrides: DrawOverrides,
+}
+
+impl Material {
+    pub fn new(shader: MaterialShader) -> Material {
+        Material {
+            shader,
+            parameters: HashMap::new(),
+            draw_overrides: DrawOverrides::default(),
+        }
+    }
+
+    //The built-in PBR shader with a white, fully rough dielectric, every value can be changed with the setters.
+    //Parameters are albedo (colour), metallic, roughness, occlusion (floats) and emissive (vec3),
+    //textures set through set_pbr_map are multiplied with them.
+    pub fn pbr() -> Material {
+        let mut material = Material::new(MaterialShader::Builtin(Shader::PBR, Vec::new()));
+        material.set_color("albedo", Vec4n::new(1.0, 1.0, 1.0, 1.0));
+        material.set_float("metallic", 0.0);
+        material.set_float("roughness", 1.0);
+        material.set_float("occlusion", 1.0);
+        material.set_vec3("emissive", Vec3n::zero());
+        material
+    }
+
+    //Binds a texture to one of the PBR shader's maps and switches to the shader variant that samples it
+    pub fn set_pbr_map(&mut self, map: PbrMap, texture: Rc<Texture>) {
+        let (name, feature) = map.get_binding();
+        self.set_texture(name, texture);
+        self.add_feature(HAS_UV);
+        self.add_feature(feature);
+    }
+
+    //Adds a feature key to the shader variant this material is drawn with
+    pub fn add_feature(&mut self, feature: &str) {
+        let features = match &mut self.shader {
+            MaterialShader::Builtin(_, features) => features,
+            MaterialShader::Dynamic(_, features) => features,
+        };
+        if !features.iter().any(|f| f == feature) {
+            features.push(feature.to_string());
+        }
+    }
+
+    pub fn get_shader(&self) -> &MaterialShader {
+        &self.shader
+    }
+
+    pub fn set_shader(&mut self, shader: MaterialShader) {
+        self.shader = shader;
+    }
+
+    //Gets the program for this material's shader variant, compiling it on first use
+    pub fn get_program<'a>(
+        &self,
+        display: &glium::Display,
+        variants: &'a mut ShaderVariantCache,
+    ) -> Result<&'a glium::Program, ShaderError> {
+        match &self.shader {
+            MaterialShader::Builtin(shader, features) => {
+                let features: Vec<&str> = features.iter().map(|f| f.as_str()).collect();
+                variants.get_builtin(display, *shader, &features)
+            }
+            MaterialShader::Dynamic(name, features) => {
+                let features: Vec<&str> = features.iter().map(|f| f.as_str()).collect();
+                variants.get_dynamic(display, name, &features)
+            }
+        }
+    }
+
+    //Checks that this material's parameters together with the engine's uniforms cover everything the program uses,
+    //meant to be run when the material is first bound to a program
+    pub fn validate<U: Uniforms>(
+        &self,
+        program: &glium::Program,
+        engine_uniforms: &U,
+    ) -> Vec<ReflectionError> {
+        self.validate_with(&Shader::reflect(program), engine_uniforms)
+    }
+
+    fn validate_with<U: Uniforms>(
+        &self,
+        reflection: &ShaderReflection,
+        engine_uniforms: &U,
+    ) -> Vec<ReflectionError> {
+        reflection.validate_uniforms(&self.get_uniforms(engine_uniforms))
+    }
+
+    pub fn set_parameter(&mut self, name: &str, parameter: MaterialParameter) {
+        self.parameters.insert(name.to_string(), parameter);
+    }
+
+    pub fn get_parameter(&self, name: &str) -> Option<&MaterialParameter> {
+        self.parameters.get(name)
+    }
+
+    pub fn remove_parameter(&mut self, name: &str) -> Option<MaterialParameter> {
+        self.parameters.remove(name)
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) {
+        self.set_parameter(name, MaterialParameter::Float(value));
+    }
+
+    pub fn set_vec3(&mut self, name: &str, value: Vec3n) {
+        self.set_parameter(name, MaterialParameter::Vec3(value));
+    }
+
+    pub fn set_color(&mut self, name: &str, value: Vec4n) {
+        self.set_parameter(name, MaterialParameter::Color(value));
+    }
+
+    pub fn set_texture(&mut self, name: &str, texture: Rc<Texture>) {
+        self.set_parameter(name, MaterialParameter::Texture(texture, None));
+    }
+
+    pub fn set_texture_with_sampler(
+        &mut self,
+        name: &str,
+        texture: Rc<Texture>,
+        sampler: SamplerSettings,
+    ) {
+        self.set_parameter(
+            name,
+            MaterialParameter::Texture(texture, Some(sampler.get_sampler_behavior())),
+        );
+    }
+
+    pub fn get_draw_overrides(&mut self) -> &mut DrawOverrides {
+        &mut self.draw_overrides
+    }
+
+    //The engine's draw parameters with this material's overrides applied on top
+    pub fn get_draw_parameters<'a>(
+        &self,
+        base: &glium::DrawParameters<'a>,
+    ) -> glium::DrawParameters<'a> {
+        let overrides = &self.draw_overrides;
+        let mut parameters = base.clone();
+        if let Some(test) = overrides.depth_test {
+            parameters.depth.test = test;
+        }
+        if let Some(write) = overrides.depth_write {
+            parameters.depth.write = write;
+        }
+        if let Some(culling) = overrides.backface_culling {
+            parameters.backface_culling = culling;
+        }
+        if let Some(blend) = overrides.blend {
+            parameters.blend = blend;
+        }
+        if let Some(polygon_mode) = overrides.polygon_mode {
+            parameters.polygon_mode = polygon_mode;
+        }
+        parameters
+    }
+
+    //Combines this material's parameters with the uniforms the engine provides (model, view, perspective, lights...).
+    //A material parameter wins over an engine uniform of the same name.
+    pub fn get_uniforms<'a, U: Uniforms>(
+        &'a self,
+        engine_uniforms: &'a U,
+    ) -> MaterialUniforms<'a, U> {
+        MaterialUniforms {
+            material: self,
+            engine_uniforms,
+        }
+    }
+}
+
+pub struct MaterialUniforms<'a, U: Uniforms> {
+    material: &'a Material,
+    engine_uniforms: &'a U,
+}
+
+impl<'a, U: Uniforms> Uniforms for MaterialUniforms<'a, U> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut output: F) {
+        let parameters = &self.material.parameters;
+        self.engine_uniforms.visit_values(|name, value| {
+            if !parameters.contains_key(name) {
+                output(name, value);
+            }
+        });
+        for (name, parameter) in parameters.iter() {
+            output(name, parameter.as_uniform_value());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::shader_reflection::UniformInfo;
+    use glium::uniforms::UniformType;
+
+    #[test]
+    fn validation_counts_material_parameters_and_engine_uniforms() {
+        let uniform = |name: &str, ty| UniformInfo {
+            name: name.to_string(),
+            ty,
+            array_size: None,
+        };
+        let reflection = ShaderReflection::from_info(
+            vec![
+                uniform("model", UniformType::FloatMat4),
+                uniform("roughness", UniformType::Float),
+                uniform("metallic", UniformType::Float),
+            ],
+            Vec::new(),
+        );
+        let engine_uniforms = uniform! {model: [[1.0f32; 4]; 4], roughness: [1.0f32; 4]};
+
+        let mut material = Material::new(MaterialShader::Builtin(Shader::PBR, Vec::new()));
+        let errors = material.validate_with(&reflection, &engine_uniforms);
+        let names: Vec<String> = errors.iter().map(|err| err.to_string()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "Uniform roughness is provided, but can not be used as the Float the shader expects",
+                "The shader uses uniform metallic (Float), but nothing provides it",
+            ]
+        );
+
+        //Material parameters win over engine uniforms of the same name
+        material.set_float("roughness", 0.5);
+        material.set_float("metallic", 1.0);
+        assert!(material
+            .validate_with(&reflection, &engine_uniforms)
+            .is_empty());
+    }
+}
@@ -0,0 +1,32 @@
+use straal::{Mat4n, Vec2n, Vec3n, Vec4n};
+
+use super::projection::Projection;
+
+//The engine's default projection, Camera owns and caches its own projection
+pub fn get_perspective_matrix(target_dims: &Vec2n) -> Mat4n {
+    Projection::default().get_matrix(target_dims.x / target_dims.y, false)
+}
+
+pub fn get_view_matrix(pos: &Vec3n, dir: &Vec3n, up: &Vec3n) -> Mat4n {
+    let fwd = dir.normalized();
+    let rht = up.cross(fwd).normalized();
+    let up = fwd.cross(rht);
+    let pos = Vec3n {
+        x: -pos.dot(rht),
+        y: -pos.dot(up),
+        z: -pos.dot(fwd),
+    };
+
+    Mat4n::new_from_vec4s(
+        Vec4n::from((rht, pos.x)),
+        Vec4n::from((up, pos.y)),
+        Vec4n::from((fwd, pos.z)),
+        Vec4n::new(0.0, 0.0, 0.0, 1.0),
+    )
+}
+
+pub fn get_model_matrix(pos: &Vec3n, scale: f32) -> Mat4n {
+    Mat4n::new(
+        scale, 0.0, 0.0, pos.x, 0.0, scale, 0.0, pos.y, 0.0, 0.0, scale, pos.z, 0.0, 0.0, 0.0, 1.0,
+    )
+}
@@ -2,14 +2,49 @@ extern crate straal;
 
 use straal::{Mat3n, Mat4n, Quatn, Vec3n, Vec4n};
 
-pub use self::camera::Camera;
-pub use self::shader_helpers::Shader;
+pub use self::camera::{Camera, CameraMode};
+pub use self::camera_controllers::{FirstPersonController, LookAtController, OrbitController};
+pub use self::collision_mesh::{Aabb, CollisionMesh, TriangleHit};
+pub use self::lighting::{LightBlock, LightBuffer, LightShadow, MAX_LIGHTS};
+pub use self::material::{DrawOverrides, Material, MaterialParameter, MaterialShader, MaterialUniforms, PbrMap};
+pub use self::matrix_helpers::{get_model_matrix, get_perspective_matrix, get_view_matrix};
+pub use self::projection::Projection;
+pub use self::ray::Ray;
+pub use self::shader_helpers::{PreprocessedSource, Shader, ShaderDiagnostic, ShaderError, ShaderStage};
+pub use self::shader_reflection::{AttributeInfo, ReflectionError, ShaderReflection, UniformInfo};
+pub use self::shader_registry::ShaderRegistry;
+pub use self::shader_variants::ShaderVariantCache;
+pub use self::shadow_map::cascaded_shadows::{
+    CascadeShadowPass, CascadeShadowPassUniforms, CascadedShadowMaps, CascadedShadowUniforms, MAX_CASCADES,
+};
+pub use self::shadow_map::point_shadows::{
+    PointShadowMaps, PointShadowPass, PointShadowPassUniforms, PointShadowUniforms, MAX_POINT_SHADOWS,
+};
+pub use self::texture_manager::{
+    ColorSpace, SamplerSettings, Texture, TextureError, TextureManager, TextureSettings,
+};
 pub use self::transform::Transform;
 
 pub mod camera;
+pub mod camera_controllers;
+pub mod collision_mesh;
+pub mod lighting;
+pub mod material;
+pub mod matrix_helpers;
+pub mod projection;
+pub mod ray;
 pub mod shader_helpers;
+pub mod shader_reflection;
+pub mod shader_registry;
+pub mod shader_variants;
+pub mod shadow_map;
+pub mod texture_manager;
 pub mod transform;
 
 pub mod scene_map;
+pub use self::scene_map::components::*;
+pub use self::scene_map::node_handle::*;
 pub use self::scene_map::scene_container::*;
+pub use self::scene_map::scene_file::*;
 pub use self::scene_map::scene_node::*;
+pub use self::scene_map::scene_traversal::*;
@@ -0,0 +1,174 @@
+use glium::draw_parameters::DepthTest;
+use straal::Mat4n;
+
+//View space looks down +z, clip space depth is the OpenGL [-1, 1] range unless reversed-Z is used
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    //Vertical field of view in radians, a far plane of None puts it at infinity
+    Perspective {
+        fov: f32,
+        near: f32,
+        far: Option<f32>,
+    },
+    //Size is half the visible height in world units
+    Orthographic {
+        size: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+impl Default for Projection {
+    fn default() -> Projection {
+        Projection::Perspective {
+            fov: std::f32::consts::PI / 3.0,
+            near: 0.1,
+            far: Some(1024.0),
+        }
+    }
+}
+
+impl Projection {
+    //Aspect ratio is width over height.
+    //Reversed-Z only flips the depth direction, the near plane maps to 1 and the far plane to 0, which needs a depth test
+    //of IfMore and a depth clear of 0, see get_depth_test and get_clear_depth.
+    //It is not a precision feature: glium can not switch GL to a [0, 1] clip depth range (glClipControl),
+    //so GL still remaps [-1, 1] to [0, 1] afterwards and the depth buffer only receives [0.5, 1].
+    pub fn get_matrix(&self, aspect_ratio: f32, reversed_z: bool) -> Mat4n {
+        match *self {
+            Projection::Perspective { fov, near, far } => {
+                let f = 1.0 / (fov / 2.0).tan();
+                let (z_scale, z_offset) = match (far, reversed_z) {
+                    (Some(far), false) => (
+                        (far + near) / (far - near),
+                        -(2.0 * far * near) / (far - near),
+                    ),
+                    (None, false) => (1.0, -2.0 * near),
+                    (Some(far), true) => (-near / (far - near), (far * near) / (far - near)),
+                    (None, true) => (0.0, near),
+                };
+                Projection::build_matrix(f / aspect_ratio, f, z_scale, z_offset, 1.0, 0.0)
+            }
+            Projection::Orthographic { size, near, far } => {
+                let (z_scale, z_offset) = if reversed_z {
+                    (-1.0 / (far - near), far / (far - near))
+                } else {
+                    (2.0 / (far - near), -(far + near) / (far - near))
+                };
+                let (x_scale, y_scale) = (1.0 / (size * aspect_ratio), 1.0 / size);
+                Projection::build_matrix(x_scale, y_scale, z_scale, z_offset, 0.0, 1.0)
+            }
+        }
+    }
+
+    //Both kinds of projection only differ in these entries, w_scale picks up view space z for the perspective divide
+    #[rustfmt::skip]
+    fn build_matrix(
+        x_scale: f32,
+        y_scale: f32,
+        z_scale: f32,
+        z_offset: f32,
+        w_scale: f32,
+        w_offset: f32,
+    ) -> Mat4n {
+        Mat4n::new(
+            x_scale, 0.0, 0.0, 0.0,
+            0.0, y_scale, 0.0, 0.0,
+            0.0, 0.0, z_scale, z_offset,
+            0.0, 0.0, w_scale, w_offset,
+        )
+    }
+
+    pub fn get_near(&self) -> f32 {
+        match *self {
+            Projection::Perspective { near, .. } => near,
+            Projection::Orthographic { near, .. } => near,
+        }
+    }
+
+    //None when the far plane is at infinity
+    pub fn get_far(&self) -> Option<f32> {
+        match *self {
+            Projection::Perspective { far, .. } => far,
+            Projection::Orthographic { far, .. } => Some(far),
+        }
+    }
+
+    pub fn get_depth_test(reversed_z: bool) -> DepthTest {
+        if reversed_z {
+            DepthTest::IfMore
+        } else {
+            DepthTest::IfLess
+        }
+    }
+
+    pub fn get_clear_depth(reversed_z: bool) -> f32 {
+        if reversed_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use straal::Vec4n;
+
+    const NEAR: f32 = 0.5;
+    const FAR: f32 = 100.0;
+
+    //Normalized device depth of a point straight ahead at the given view space distance
+    fn get_depth(projection: Projection, reversed_z: bool, distance: f32) -> f32 {
+        let clip = projection.get_matrix(1.5, reversed_z) * Vec4n::new(0.0, 0.0, distance, 1.0);
+        clip.z / clip.w
+    }
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} is not close to {}", a, b);
+    }
+
+    fn perspective(far: Option<f32>) -> Projection {
+        Projection::Perspective {
+            fov: 1.0,
+            near: NEAR,
+            far,
+        }
+    }
+
+    #[test]
+    fn perspective_maps_near_and_far_to_the_clip_range() {
+        assert_near(get_depth(perspective(Some(FAR)), false, NEAR), -1.0);
+        assert_near(get_depth(perspective(Some(FAR)), false, FAR), 1.0);
+    }
+
+    #[test]
+    fn infinite_perspective_approaches_the_far_end() {
+        assert_near(get_depth(perspective(None), false, NEAR), -1.0);
+        assert!(get_depth(perspective(None), false, FAR) < 1.0);
+        assert_near(get_depth(perspective(None), false, 1e6), 1.0);
+    }
+
+    #[test]
+    fn reversed_z_maps_near_to_one_and_far_to_zero() {
+        assert_near(get_depth(perspective(Some(FAR)), true, NEAR), 1.0);
+        assert_near(get_depth(perspective(Some(FAR)), true, FAR), 0.0);
+        assert_near(get_depth(perspective(None), true, NEAR), 1.0);
+        assert!(get_depth(perspective(None), true, FAR) > 0.0);
+        assert_near(get_depth(perspective(None), true, 1e6), 0.0);
+    }
+
+    #[test]
+    fn orthographic_maps_near_and_far_to_the_clip_range() {
+        let projection = Projection::Orthographic {
+            size: 5.0,
+            near: NEAR,
+            far: FAR,
+        };
+        assert_near(get_depth(projection, false, NEAR), -1.0);
+        assert_near(get_depth(projection, false, FAR), 1.0);
+        assert_near(get_depth(projection, true, NEAR), 1.0);
+        assert_near(get_depth(projection, true, FAR), 0.0);
+    }
+}
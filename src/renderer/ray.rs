@@ -0,0 +1,21 @@
+use straal::Vec3n;
+
+//Half line in world space, the direction is always normalized
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Vec3n,
+    pub direction: Vec3n,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3n, direction: Vec3n) -> Ray {
+        Ray {
+            origin,
+            direction: direction.normalized(),
+        }
+    }
+
+    pub fn get_point(&self, distance: f32) -> Vec3n {
+        self.origin + self.direction * distance
+    }
+}
@@ -0,0 +1,215 @@
+use super::node_handle::NodeHandle;
+use crate::renderer::CollisionMesh;
+use serde::{Deserialize, Serialize};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::rc::Rc;
+use straal::Vec3n;
+
+//Draws a mesh with a shader, the references are resolved by whoever renders the scene
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshRenderer {
+    pub mesh: String,
+    pub shader: String,
+    pub material: Option<String>,
+}
+
+//Gives a node its own mesh for SceneContainer::raycast, e.g. a simplified one.
+//Without it a MeshRenderer node is picked with the mesh given to SceneContainer::set_collision_mesh for its mesh path.
+#[derive(Clone)]
+pub struct MeshCollider {
+    pub mesh: Rc<CollisionMesh>,
+}
+
+//How the strength of a light falls off over distance: 1 / (constant + linear * d + quadratic * d^2)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    //Falls off to roughly 1% of its strength at the given range
+    pub fn from_range(range: f32) -> Attenuation {
+        Attenuation {
+            constant: 1.0,
+            linear: 4.5 / range,
+            quadratic: 75.0 / (range * range),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    //Shines along the node's forward direction, its position does not matter
+    Directional,
+    Point(Attenuation),
+    //Shines along the node's forward direction, the angles are in radians from the centre of the cone to its edge.
+    //Light fades out between the inner and the outer angle.
+    Spot {
+        attenuation: Attenuation,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+//Lights the scene from the position and orientation of the node it is attached to
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3n,
+    pub intensity: f32,
+}
+
+//Makes the point light on the same node cast shadows into a cube map
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointShadow {
+    //Size of every cube face in pixels
+    pub resolution: u32,
+    pub near: f32,
+    //Nothing further away than this casts a shadow
+    pub far: f32,
+    //Pulls the compared distance towards the light by this many world units to avoid shadow acne
+    pub bias: f32,
+    //Radius in world units of the percentage closer filtering kernel, 0 gives hard shadows
+    pub pcf_radius: f32,
+}
+
+impl Default for PointShadow {
+    fn default() -> PointShadow {
+        PointShadow {
+            resolution: 512,
+            near: 0.05,
+            far: 25.0,
+            bias: 0.05,
+            pcf_radius: 0.02,
+        }
+    }
+}
+
+//Makes the directional light on the same node cast cascaded shadows, only the first one in the scene is used
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalShadow {
+    //Size of every cascade in pixels
+    pub resolution: u32,
+    //Clamped to MAX_CASCADES
+    pub cascade_count: usize,
+    //Distance from the camera up to which shadows are drawn
+    pub max_distance: f32,
+    //Blends between evenly spaced splits at 0 and logarithmic splits at 1
+    pub split_lambda: f32,
+    //How far behind the camera's view casters are still picked up, in world units
+    pub caster_distance: f32,
+    //Depth bias in the [0, 1] range of the shadow map
+    pub bias: f32,
+    //Radius in texels of the percentage closer filtering kernel, 0 gives hard shadows
+    pub pcf_radius: u32,
+    //Fraction at the end of every cascade that fades into the next one
+    pub blend: f32,
+}
+
+impl Default for DirectionalShadow {
+    fn default() -> DirectionalShadow {
+        DirectionalShadow {
+            resolution: 1024,
+            cascade_count: 4,
+            max_distance: 50.0,
+            split_lambda: 0.75,
+            caster_distance: 20.0,
+            bias: 0.002,
+            pcf_radius: 1,
+            blend: 0.1,
+        }
+    }
+}
+
+//Type erased access to the storage of a single component type
+trait ComponentStorage {
+    fn remove_node(&mut self, node_id: NodeHandle);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> ComponentStorage for HashMap<NodeHandle, T> {
+    fn remove_node(&mut self, node_id: NodeHandle) {
+        self.remove(&node_id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+//Holds at most one component of every type per node, any 'static type can be used as a component
+#[derive(Default)]
+pub struct ComponentStore {
+    storages: HashMap<TypeId, Box<dyn ComponentStorage>>,
+}
+
+impl ComponentStore {
+    pub fn new() -> ComponentStore {
+        ComponentStore {
+            storages: HashMap::new(),
+        }
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&HashMap<NodeHandle, T>> {
+        self.storages
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<HashMap<NodeHandle, T>>()
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut HashMap<NodeHandle, T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(HashMap::<NodeHandle, T>::new()))
+            .as_any_mut()
+            .downcast_mut::<HashMap<NodeHandle, T>>()
+            .unwrap()
+    }
+
+    //Returns the component of the same type that was attached before, if any
+    pub fn insert<T: 'static>(&mut self, node_id: NodeHandle, component: T) -> Option<T> {
+        self.storage_mut::<T>().insert(node_id, component)
+    }
+
+    pub fn get<T: 'static>(&self, node_id: NodeHandle) -> Option<&T> {
+        self.storage::<T>()?.get(&node_id)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, node_id: NodeHandle) -> Option<&mut T> {
+        self.storage_mut::<T>().get_mut(&node_id)
+    }
+
+    pub fn remove<T: 'static>(&mut self, node_id: NodeHandle) -> Option<T> {
+        self.storage_mut::<T>().remove(&node_id)
+    }
+
+    //Drops every component attached to the node
+    pub fn remove_node(&mut self, node_id: NodeHandle) {
+        for storage in self.storages.values_mut() {
+            storage.remove_node(node_id);
+        }
+    }
+
+    //All nodes with a component of the given type, in no particular order
+    pub fn query<T: 'static>(&self) -> Vec<(NodeHandle, &T)> {
+        match self.storage::<T>() {
+            Some(storage) => storage.iter().map(|(id, c)| (*id, c)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> Vec<(NodeHandle, &mut T)> {
+        self.storage_mut::<T>()
+            .iter_mut()
+            .map(|(id, c)| (*id, c))
+            .collect()
+    }
+}
@@ -1,2 +1,6 @@
+pub mod components;
+pub mod node_handle;
 pub mod scene_container;
+pub mod scene_file;
 pub mod scene_node;
+pub mod scene_traversal;
@@ -0,0 +1,35 @@
+use core::fmt;
+
+//Refers to a node inside a SceneContainer. Slots of removed nodes get reused,
+//the generation makes sure a handle to a removed node goes stale instead of pointing at the new occupant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeHandle {
+    index: u32,
+    generation: u32,
+}
+
+impl NodeHandle {
+    //Placeholder for nodes that have not been added to a container yet, never resolves to a node
+    pub(in crate::renderer::scene_map) const INVALID: NodeHandle = NodeHandle {
+        index: std::u32::MAX,
+        generation: std::u32::MAX,
+    };
+
+    pub(in crate::renderer::scene_map) fn new(index: u32, generation: u32) -> NodeHandle {
+        NodeHandle { index, generation }
+    }
+
+    pub fn get_index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl fmt::Display for NodeHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
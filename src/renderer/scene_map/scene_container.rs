@@ -1,44 +1,940 @@
+use super::components::{ComponentStore, MeshCollider, MeshRenderer};
+use super::node_handle::NodeHandle;
 use super::scene_node::SceneNode;
+use super::scene_traversal::{AncestorIter, BreadthFirstIter, PostOrderIter, PreOrderIter};
+use crate::renderer::collision_mesh::{transform_triangle, CollisionMesh};
+use crate::renderer::Ray;
 use core::fmt;
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::rc::Rc;
+use straal::{Mat4n, Vec3n};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneError {
+    MissingNode(NodeHandle),
+    CyclicHierarchy {
+        node: NodeHandle,
+        parent: NodeHandle,
+    },
+    DanglingParent {
+        node: NodeHandle,
+        parent: NodeHandle,
+    },
+    DanglingChild {
+        node: NodeHandle,
+        child: NodeHandle,
+    },
+    MismatchedLink {
+        parent: NodeHandle,
+        child: NodeHandle,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::MissingNode(id) => write!(f, "There is no live node for handle {}", id),
+            SceneError::CyclicHierarchy { node, parent } => write!(
+                f,
+                "Node {} can not be parented to {}, since {} is one of its descendants",
+                node, parent, parent
+            ),
+            SceneError::DanglingParent { node, parent } => write!(
+                f,
+                "Node {} refers to parent {}, which is not in the container",
+                node, parent
+            ),
+            SceneError::DanglingChild { node, child } => write!(
+                f,
+                "Node {} refers to child {}, which is not in the container",
+                node, child
+            ),
+            SceneError::MismatchedLink { parent, child } => write!(
+                f,
+                "Node {} and node {} disagree about being parent and child",
+                parent, child
+            ),
+        }
+    }
+}
+
+//What happens to the children of a node when it gets removed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemovalMode {
+    //Removes the node along with every node below it
+    Subtree,
+    //Hands the children over to the parent of the removed node, keeping their world placement
+    ReparentChildren,
+}
+
+//The nearest thing a ray ran into, everything is in world space
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    pub node: NodeHandle,
+    pub triangle: usize,
+    //Weights of the triangle's three vertices at the hit position
+    pub barycentric: Vec3n,
+    pub position: Vec3n,
+    //Face normal following the triangle's winding
+    pub normal: Vec3n,
+    pub distance: f32,
+}
 
 #[derive(Debug)]
+struct NodeSlot {
+    node: Option<Rc<RefCell<SceneNode>>>,
+    generation: u32,
+}
+
 pub struct SceneContainer {
-    members: HashMap<u64, Rc<RefCell<SceneNode>>>,
-    cur_id: u64,
+    slots: Vec<NodeSlot>,
+    free_slots: Vec<u32>,
+    node_count: usize,
+    names: HashMap<String, Vec<NodeHandle>>,
+    components: ComponentStore,
+    //Picking meshes by the mesh path of a MeshRenderer, used for nodes without a MeshCollider of their own
+    collision_meshes: HashMap<String, Rc<CollisionMesh>>,
+}
+
+impl fmt::Debug for SceneContainer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "nodes: {:?}, free slots: {:?}",
+            self.get_node_ids(),
+            self.free_slots
+        )
+    }
 }
 
 impl SceneContainer {
     pub fn new() -> Rc<RefCell<SceneContainer>> {
         Rc::new(RefCell::new(SceneContainer {
-            members: HashMap::new(),
-            cur_id: 0,
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+            node_count: 0,
+            names: HashMap::new(),
+            components: ComponentStore::new(),
+            collision_meshes: HashMap::new(),
         }))
     }
 
-    pub fn get_node(&self, node_id: u64) -> Option<Rc<RefCell<SceneNode>>> {
-        Some(self.members.get(&node_id)?.clone())
+    //Returns None for handles whose node has been removed, even when the slot has been reused since
+    pub fn get_node(&self, node_id: NodeHandle) -> Option<Rc<RefCell<SceneNode>>> {
+        let slot = self.slots.get(node_id.get_index() as usize)?;
+        if slot.generation != node_id.get_generation() {
+            return None;
+        }
+        slot.node.clone()
     }
 
-    pub fn add_node(&mut self, node: Rc<RefCell<SceneNode>>) -> u64 {
-        let node_id = self.cur_id;
-        node.as_ref().borrow_mut().set_id(node_id);
-        self.members.insert(self.cur_id, node.to_owned());
-        self.cur_id += 1;
+    pub fn add_node(&mut self, node: Rc<RefCell<SceneNode>>) -> NodeHandle {
+        let index = match self.free_slots.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(NodeSlot {
+                    node: None,
+                    generation: 0,
+                });
+                (self.slots.len() - 1) as u32
+            }
+        };
+        let slot = &mut self.slots[index as usize];
+        let node_id = NodeHandle::new(index, slot.generation);
+        let name = {
+            let mut node = node.as_ref().borrow_mut();
+            node.set_id(node_id);
+            node.get_name().to_string()
+        };
+        slot.node = Some(node);
+        self.node_count += 1;
+        self.index_name(name, node_id);
         node_id
     }
 
-    pub fn remove_node(&mut self, node_id: u64) {
-        self.members.remove(&node_id);
+    //Empties the slot of a node and bumps its generation, so every outstanding handle to it goes stale
+    fn free_slot(&mut self, node_id: NodeHandle) {
+        let name = match self.get_node(node_id) {
+            Some(node) => (*node).borrow().get_name().to_string(),
+            None => return,
+        };
+        self.unindex_name(&name, node_id);
+        self.components.remove_node(node_id);
+        let slot = &mut self.slots[node_id.get_index() as usize];
+        slot.node = None;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(node_id.get_index());
+        self.node_count -= 1;
+    }
+
+    //Handles of every node currently in the container
+    pub fn get_node_ids(&self) -> Vec<NodeHandle> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| slot.node.is_some())
+            .map(|(index, slot)| NodeHandle::new(index as u32, slot.generation))
+            .collect()
+    }
+
+    fn index_name(&mut self, name: String, node_id: NodeHandle) {
+        self.names
+            .entry(name)
+            .or_insert_with(Vec::new)
+            .push(node_id);
+    }
+
+    fn unindex_name(&mut self, name: &str, node_id: NodeHandle) {
+        let now_empty = match self.names.get_mut(name) {
+            Some(ids) => {
+                ids.retain(|id| *id != node_id);
+                ids.is_empty()
+            }
+            None => false,
+        };
+        if now_empty {
+            self.names.remove(name);
+        }
+    }
+
+    //Renames a node, this has to go through the container so name lookups stay current
+    pub fn rename_node(&mut self, node_id: NodeHandle, name: &str) -> Result<(), SceneError> {
+        let node = self
+            .get_node(node_id)
+            .ok_or(SceneError::MissingNode(node_id))?;
+        let old_name = (*node).borrow().get_name().to_string();
+        self.unindex_name(&old_name, node_id);
+        (*node).borrow_mut().set_name(name);
+        self.index_name(name.to_string(), node_id);
+        Ok(())
+    }
+
+    //The first node that was given this name, when several nodes share it
+    pub fn find_by_name(&self, name: &str) -> Option<NodeHandle> {
+        self.names.get(name)?.first().cloned()
+    }
+
+    pub fn find_all_by_name(&self, name: &str) -> Vec<NodeHandle> {
+        match self.names.get(name) {
+            Some(ids) => ids.clone(),
+            None => Vec::new(),
+        }
+    }
+
+    //Resolves a slash separated path like "robot/arm/hand", each segment naming a child of the previous one.
+    //The path starts at the given node, or at the roots when there is none, ".." steps up to the parent.
+    pub fn find_by_path(&self, start: Option<NodeHandle>, path: &str) -> Option<NodeHandle> {
+        let mut current = start;
+        for segment in path.split('/').filter(|s| !s.is_empty() && *s != ".") {
+            if segment == ".." {
+                let node = self.get_node(current?)?;
+                current = (*node).borrow().get_parent_id();
+                continue;
+            }
+            current =
+                Some(self.names.get(segment)?.iter().cloned().find(
+                    |id| match self.get_node(*id) {
+                        Some(node) => (*node).borrow().get_parent_id() == current,
+                        None => false,
+                    },
+                )?);
+        }
+        current
+    }
+
+    //Attaches a component to a node, replacing and returning the one of the same type that was there before
+    pub fn add_component<T: 'static>(
+        &mut self,
+        node_id: NodeHandle,
+        component: T,
+    ) -> Result<Option<T>, SceneError> {
+        if !self.has_node(node_id) {
+            return Err(SceneError::MissingNode(node_id));
+        }
+        Ok(self.components.insert(node_id, component))
+    }
+
+    pub fn get_component<T: 'static>(&self, node_id: NodeHandle) -> Option<&T> {
+        self.components.get(node_id)
+    }
+
+    pub fn get_component_mut<T: 'static>(&mut self, node_id: NodeHandle) -> Option<&mut T> {
+        self.components.get_mut(node_id)
+    }
+
+    pub fn remove_component<T: 'static>(&mut self, node_id: NodeHandle) -> Option<T> {
+        self.components.remove(node_id)
+    }
+
+    pub fn has_component<T: 'static>(&self, node_id: NodeHandle) -> bool {
+        self.get_component::<T>(node_id).is_some()
+    }
+
+    //Every node carrying a component of the given type, e.g. query::<MeshRenderer>() for everything drawable
+    pub fn query<T: 'static>(&self) -> Vec<(NodeHandle, &T)> {
+        self.components.query()
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> Vec<(NodeHandle, &mut T)> {
+        self.components.query_mut()
+    }
+
+    //Handles of every node without a parent, in the order they were added
+    pub fn get_root_ids(&self) -> Vec<NodeHandle> {
+        self.get_node_ids()
+            .into_iter()
+            .filter(|id| {
+                self.get_node(*id)
+                    .map_or(false, |node| (*node).borrow().get_parent_id().is_none())
+            })
+            .collect()
+    }
+
+    pub fn iter_pre_order(&self) -> PreOrderIter {
+        PreOrderIter::new(self, self.get_root_ids(), 0)
+    }
+
+    pub fn iter_post_order(&self) -> PostOrderIter {
+        PostOrderIter::new(self, self.get_root_ids(), 0)
+    }
+
+    pub fn iter_breadth_first(&self) -> BreadthFirstIter {
+        BreadthFirstIter::new(self, self.get_root_ids(), 0)
+    }
+
+    //Parent first, then grandparent and so on, the node itself is not included
+    pub fn iter_ancestors(&self, node_id: NodeHandle) -> AncestorIter {
+        AncestorIter::new(self, node_id)
+    }
+
+    //Every node below the given one in pre-order, the node itself is not included
+    pub fn iter_descendants(&self, node_id: NodeHandle) -> PreOrderIter {
+        let children = match self.get_node(node_id) {
+            None => Vec::new(),
+            Some(node) => (*node).borrow().get_children_ids_unchecked(),
+        };
+        PreOrderIter::new(self, children, 1)
+    }
+
+    //Walks the whole scene in pre-order, handing every node to the visitor together with its depth
+    //and the world matrix of its parent, so world matrices are composed once per node for the whole pass
+    pub fn visit_with_world<F>(&self, mut visitor: F)
+    where
+        F: FnMut(NodeHandle, usize, &mut SceneNode, &Mat4n),
+    {
+        let mut parent_worlds: Vec<Mat4n> = Vec::new();
+        for (node_id, depth) in self.iter_pre_order() {
+            parent_worlds.truncate(depth);
+            let node = match self.get_node(node_id) {
+                Some(node) => node,
+                None => continue,
+            };
+            let mut node = (*node).borrow_mut();
+            let parent_world = match parent_worlds.last() {
+                Some(world) => *world,
+                None => Mat4n::identity(),
+            };
+            visitor(node_id, depth, &mut node, &parent_world);
+            parent_worlds.push(parent_world * node.get_transform().get_local_matrix());
+        }
+    }
+
+    //Makes every node drawing the given mesh path through a MeshRenderer pickable with this collision mesh
+    pub fn set_collision_mesh(&mut self, mesh_path: &str, mesh: Rc<CollisionMesh>) {
+        self.collision_meshes.insert(mesh_path.to_string(), mesh);
+    }
+
+    pub fn get_collision_mesh(&self, mesh_path: &str) -> Option<Rc<CollisionMesh>> {
+        self.collision_meshes.get(mesh_path).cloned()
+    }
+
+    //A MeshCollider wins, otherwise the collision mesh set for the mesh the node draws
+    fn get_collider(&self, node_id: NodeHandle) -> Option<Rc<CollisionMesh>> {
+        match self.get_component::<MeshCollider>(node_id) {
+            Some(collider) => Some(collider.mesh.clone()),
+            None => {
+                let mesh_renderer = self.get_component::<MeshRenderer>(node_id)?;
+                self.get_collision_mesh(&mesh_renderer.mesh)
+            }
+        }
+    }
+
+    //Finds the nearest node along the ray that has a MeshCollider, or a MeshRenderer whose mesh was given to
+    //set_collision_mesh. World bounding boxes rule nodes out before their triangles get tested.
+    //get_ids_without_collider lists the drawn nodes that can not be hit.
+    pub fn raycast(&self, ray: &Ray) -> Option<RaycastHit> {
+        let mut nearest: Option<(NodeHandle, Mat4n, f32, usize, f32, f32)> = None;
+        self.visit_with_world(|node_id, _, node, parent_world| {
+            let collider = match self.get_collider(node_id) {
+                Some(collider) => collider,
+                None => return,
+            };
+            let world = *parent_world * node.get_transform().get_local_matrix();
+            let nearest_distance = nearest.map_or(std::f32::INFINITY, |n| n.2);
+            match collider.get_bounds().transformed(&world).intersect_ray(ray) {
+                Some(distance) if distance < nearest_distance => {}
+                _ => return,
+            }
+            if let Some(hit) = collider.intersect_world_ray(ray, &world) {
+                if hit.distance < nearest_distance {
+                    nearest = Some((node_id, world, hit.distance, hit.triangle, hit.u, hit.v));
+                }
+            }
+        });
+
+        let (node, world, distance, triangle, u, v) = nearest?;
+        let collider = self.get_collider(node)?;
+        let [a, b, c] = transform_triangle(&world, collider.get_triangle(triangle)?);
+        Some(RaycastHit {
+            node,
+            triangle,
+            barycentric: Vec3n::new(1.0 - u - v, u, v),
+            position: ray.get_point(distance),
+            normal: (b - a).cross(c - a).normalized(),
+            distance,
+        })
+    }
+
+    //Nodes that are drawn through a MeshRenderer but can not be hit by raycast
+    pub fn get_ids_without_collider(&self) -> Vec<NodeHandle> {
+        self.query::<MeshRenderer>()
+            .into_iter()
+            .map(|(node_id, _)| node_id)
+            .filter(|node_id| self.get_collider(*node_id).is_none())
+            .collect()
+    }
+
+    //Removes a node while keeping every remaining parent and child link valid, returns the ids that were removed
+    pub fn remove_node(
+        &mut self,
+        node_id: NodeHandle,
+        mode: RemovalMode,
+    ) -> Result<Vec<NodeHandle>, SceneError> {
+        let node = self
+            .get_node(node_id)
+            .ok_or(SceneError::MissingNode(node_id))?;
+
+        let removed = match mode {
+            RemovalMode::Subtree => {
+                let mut removed = Vec::new();
+                let mut pending = vec![node_id];
+                while let Some(id) = pending.pop() {
+                    if let Some(member) = self.get_node(id) {
+                        pending.extend((*member).borrow().get_children_ids_unchecked());
+                        removed.push(id);
+                    }
+                }
+                removed
+            }
+            RemovalMode::ReparentChildren => {
+                let (parent_id, children) = {
+                    let node = (*node).borrow();
+                    (node.get_parent_id(), node.get_children_ids_unchecked())
+                };
+                for child_id in children {
+                    self.reparent(child_id, parent_id, true)?;
+                }
+                vec![node_id]
+            }
+        };
+
+        self.unlink(node_id);
+        for id in removed.iter() {
+            self.free_slot(*id);
+        }
+
+        Ok(removed)
     }
 
-    pub fn has_node(&self, node_id: u64) -> bool {
-        self.members.contains_key(&node_id)
+    pub fn has_node(&self, node_id: NodeHandle) -> bool {
+        self.get_node(node_id).is_some()
     }
 
     pub fn node_count(&self) -> usize {
-        self.members.len()
+        self.node_count
+    }
+
+    //Moves a node under a new parent (or to the root when None), updating both the old and new parent.
+    //When keep_world_transform is set, the local transform is recomputed so the node does not move in the world.
+    pub fn reparent(
+        &mut self,
+        node_id: NodeHandle,
+        new_parent: Option<NodeHandle>,
+        keep_world_transform: bool,
+    ) -> Result<(), SceneError> {
+        let node = self
+            .get_node(node_id)
+            .ok_or(SceneError::MissingNode(node_id))?;
+
+        if let Some(parent_id) = new_parent {
+            if !self.has_node(parent_id) {
+                return Err(SceneError::MissingNode(parent_id));
+            }
+            if self.is_ancestor_or_self(node_id, parent_id) {
+                return Err(SceneError::CyclicHierarchy {
+                    node: node_id,
+                    parent: parent_id,
+                });
+            }
+        }
+
+        let world = if keep_world_transform {
+            self.update_world_transform(node_id);
+            let mut node = (*node).borrow_mut();
+            let transform = node.get_transform();
+            Some((
+                transform.get_world_position(),
+                transform.get_world_rotation(),
+                transform.get_world_scale(),
+            ))
+        } else {
+            None
+        };
+
+        self.unlink(node_id);
+        if let Some(parent_id) = new_parent {
+            self.link(node_id, parent_id);
+        }
+
+        if let Some((position, rotation, scale)) = world {
+            self.update_world_transform(node_id);
+            let mut node = (*node).borrow_mut();
+            let transform = node.get_transform();
+            transform.set_world_position(position);
+            transform.set_world_rotation(rotation);
+            transform.set_world_scale(scale);
+        }
+
+        Ok(())
+    }
+
+    //Checks every parent and child link in the container, reporting each one that points at a missing node
+    //or that is not mirrored on the other side
+    pub fn validate(&self) -> Vec<SceneError> {
+        let mut errors = Vec::new();
+        for id in self.get_node_ids().iter() {
+            let node = self.get_node(*id).unwrap();
+            let node = (*node).borrow();
+            if let Some(parent_id) = node.get_parent_id() {
+                match self.get_node(parent_id) {
+                    None => errors.push(SceneError::DanglingParent {
+                        node: *id,
+                        parent: parent_id,
+                    }),
+                    Some(parent) => {
+                        if !(*parent).borrow().get_children_ids_unchecked().contains(id) {
+                            errors.push(SceneError::MismatchedLink {
+                                parent: parent_id,
+                                child: *id,
+                            });
+                        }
+                    }
+                }
+            }
+            for child_id in node.get_children_ids_unchecked() {
+                match self.get_node(child_id) {
+                    None => errors.push(SceneError::DanglingChild {
+                        node: *id,
+                        child: child_id,
+                    }),
+                    Some(child) => {
+                        if (*child).borrow().get_parent_id() != Some(*id) {
+                            errors.push(SceneError::MismatchedLink {
+                                parent: *id,
+                                child: child_id,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    //Whether ancestor_id is node_id itself or can be reached by walking up from descendant_id
+    fn is_ancestor_or_self(&self, ancestor_id: NodeHandle, descendant_id: NodeHandle) -> bool {
+        let mut current = Some(descendant_id);
+        while let Some(id) = current {
+            if id == ancestor_id {
+                return true;
+            }
+            current = self
+                .get_node(id)
+                .and_then(|node| (*node).borrow().get_parent_id());
+        }
+        false
+    }
+
+    //Attaches a node to a parent on both sides of the link, the node is expected to be unlinked
+    pub(in crate::renderer::scene_map) fn link(
+        &mut self,
+        node_id: NodeHandle,
+        parent_id: NodeHandle,
+    ) {
+        if let (Some(node), Some(parent)) = (self.get_node(node_id), self.get_node(parent_id)) {
+            (*node).borrow_mut().set_parent_id(Some(parent_id));
+            (*parent).borrow_mut().add_child_id(node_id);
+        }
+    }
+
+    //Detaches a node from its current parent on both sides of the link
+    fn unlink(&mut self, node_id: NodeHandle) {
+        if let Some(node) = self.get_node(node_id) {
+            let mut node = (*node).borrow_mut();
+            if let Some(parent) = node.get_parent_id().and_then(|id| self.get_node(id)) {
+                (*parent).borrow_mut().remove_child_id(node_id);
+            }
+            node.set_parent_id(None);
+        }
+    }
+
+    //Brings the cached world transforms along the parent chain up to date without borrowing the container again,
+    //SceneNode::update_world_transform uses this for the chain above the node
+    pub fn update_world_transform(&self, node_id: NodeHandle) {
+        let node = match self.get_node(node_id) {
+            Some(node) => node,
+            None => return,
+        };
+        let parent_id = (*node).borrow().get_parent_id();
+        if let Some(parent_id) = parent_id {
+            self.update_world_transform(parent_id);
+        }
+        let parent = parent_id.and_then(|id| self.get_node(id));
+        (*node).borrow_mut().place_in_parent(parent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use straal::{Quatn, Vec3n};
+
+    //root
+    //├── arm
+    //│   └── hand
+    //└── leg
+    fn build_scene() -> (Rc<RefCell<SceneContainer>>, [NodeHandle; 4]) {
+        let container = SceneContainer::new();
+        let root = SceneNode::create_new(container.clone(), Some("root"), None);
+        let arm = SceneNode::create_new(container.clone(), Some("arm"), Some(root));
+        let hand = SceneNode::create_new(container.clone(), Some("hand"), Some(arm));
+        let leg = SceneNode::create_new(container.clone(), Some("leg"), Some(root));
+        (container, [root, arm, hand, leg])
+    }
+
+    fn get_children(container: &SceneContainer, node_id: NodeHandle) -> Vec<NodeHandle> {
+        let node = container.get_node(node_id).unwrap();
+        let node = (*node).borrow();
+        node.get_children_ids_unchecked()
+    }
+
+    fn get_parent(container: &SceneContainer, node_id: NodeHandle) -> Option<NodeHandle> {
+        let node = container.get_node(node_id).unwrap();
+        let node = (*node).borrow();
+        node.get_parent_id()
+    }
+
+    fn get_names<I: Iterator<Item = (NodeHandle, usize)>>(
+        container: &SceneContainer,
+        nodes: I,
+    ) -> Vec<(String, usize)> {
+        nodes
+            .map(|(id, depth)| {
+                let node = container.get_node(id).unwrap();
+                let name = (*node).borrow().get_name().to_string();
+                (name, depth)
+            })
+            .collect()
+    }
+
+    fn assert_near(a: Vec3n, b: Vec3n) {
+        let close =
+            (a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5 && (a.z - b.z).abs() < 1e-5;
+        assert!(close, "{:?} is not close to {:?}", a, b);
+    }
+
+    #[test]
+    fn subtree_removal_takes_descendants_along() {
+        let (container, [root, arm, hand, leg]) = build_scene();
+        let mut scene = container.borrow_mut();
+
+        let mut removed = scene.remove_node(arm, RemovalMode::Subtree).unwrap();
+        removed.sort_by_key(|id| id.get_index());
+        assert_eq!(removed, vec![arm, hand]);
+        assert_eq!(scene.node_count(), 2);
+        assert!(!scene.has_node(arm));
+        assert!(!scene.has_node(hand));
+        assert_eq!(get_children(&scene, root), vec![leg]);
+        assert_eq!(scene.find_by_name("hand"), None);
+        assert!(scene.validate().is_empty());
+    }
+
+    #[test]
+    fn reparent_removal_keeps_children_in_place() {
+        let (container, [root, arm, hand, leg]) = build_scene();
+        let mut scene = container.borrow_mut();
+        {
+            let arm = scene.get_node(arm).unwrap();
+            let hand = scene.get_node(hand).unwrap();
+            (*arm)
+                .borrow_mut()
+                .get_transform()
+                .set_local_position(Vec3n::new(1.0, 0.0, 0.0));
+            (*hand)
+                .borrow_mut()
+                .get_transform()
+                .set_local_position(Vec3n::new(0.0, 2.0, 0.0));
+        }
+
+        let removed = scene
+            .remove_node(arm, RemovalMode::ReparentChildren)
+            .unwrap();
+        assert_eq!(removed, vec![arm]);
+        assert_eq!(scene.node_count(), 3);
+        assert_eq!(get_parent(&scene, hand), Some(root));
+        assert_eq!(get_children(&scene, root), vec![leg, hand]);
+        assert!(scene.validate().is_empty());
+
+        let hand = scene.get_node(hand).unwrap();
+        let position = (*hand).borrow_mut().get_transform().get_local_position();
+        assert_near(position, Vec3n::new(1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn removing_a_missing_node_fails() {
+        let (container, [_, arm, _, _]) = build_scene();
+        let mut scene = container.borrow_mut();
+        scene.remove_node(arm, RemovalMode::Subtree).unwrap();
+        assert_eq!(
+            scene.remove_node(arm, RemovalMode::Subtree),
+            Err(SceneError::MissingNode(arm))
+        );
+    }
+
+    #[test]
+    fn validate_reports_corrupted_links() {
+        let (container, [root, arm, hand, leg]) = build_scene();
+        let mut scene = container.borrow_mut();
+        let stale = {
+            let removed = scene.remove_node(leg, RemovalMode::Subtree).unwrap();
+            removed[0]
+        };
+
+        (*scene.get_node(arm).unwrap())
+            .borrow_mut()
+            .remove_child_id(hand);
+        (*scene.get_node(root).unwrap())
+            .borrow_mut()
+            .add_child_id(stale);
+
+        let errors = scene.validate();
+        assert_eq!(errors.len(), 2);
+        assert!(errors.contains(&SceneError::MismatchedLink {
+            parent: arm,
+            child: hand,
+        }));
+        assert!(errors.contains(&SceneError::DanglingChild {
+            node: root,
+            child: stale,
+        }));
+    }
+
+    #[test]
+    fn reparent_rejects_cycles() {
+        let (container, [root, arm, hand, _]) = build_scene();
+        let mut scene = container.borrow_mut();
+
+        assert_eq!(
+            scene.reparent(root, Some(hand), false),
+            Err(SceneError::CyclicHierarchy {
+                node: root,
+                parent: hand,
+            })
+        );
+        assert_eq!(
+            scene.reparent(arm, Some(arm), false),
+            Err(SceneError::CyclicHierarchy {
+                node: arm,
+                parent: arm,
+            })
+        );
+        assert_eq!(get_parent(&scene, root), None);
+        assert_eq!(get_parent(&scene, arm), Some(root));
+        assert!(scene.validate().is_empty());
+    }
+
+    #[test]
+    fn reparent_moves_both_sides_of_the_link() {
+        let (container, [root, arm, hand, leg]) = build_scene();
+        let mut scene = container.borrow_mut();
+
+        scene.reparent(hand, Some(leg), false).unwrap();
+        assert_eq!(get_parent(&scene, hand), Some(leg));
+        assert!(get_children(&scene, arm).is_empty());
+        assert_eq!(get_children(&scene, leg), vec![hand]);
+
+        scene.reparent(arm, None, false).unwrap();
+        assert_eq!(scene.get_root_ids(), vec![root, arm]);
+        assert!(scene.validate().is_empty());
+    }
+
+    #[test]
+    fn reparent_keeps_world_transform_under_a_rotated_and_scaled_parent() {
+        let (container, [_, _, hand, leg]) = build_scene();
+        let mut scene = container.borrow_mut();
+        let hand_node = scene.get_node(hand).unwrap();
+        {
+            let leg = scene.get_node(leg).unwrap();
+            let mut leg = (*leg).borrow_mut();
+            let transform = leg.get_transform();
+            transform.set_local_position(Vec3n::new(1.0, 0.0, 0.0));
+            transform.set_local_rotation(Quatn::get_quat_from_angle_axis(
+                std::f32::consts::PI,
+                Vec3n::up(),
+            ));
+            transform.set_local_scale(Vec3n::all(2.0));
+            (*hand_node)
+                .borrow_mut()
+                .get_transform()
+                .set_local_position(Vec3n::new(3.0, 1.0, 0.0));
+        }
+
+        scene.reparent(hand, Some(leg), true).unwrap();
+        assert_eq!(get_parent(&scene, hand), Some(leg));
+        scene.update_world_transform(hand);
+
+        let mut hand = (*hand_node).borrow_mut();
+        let transform = hand.get_transform();
+        assert_near(transform.get_local_position(), Vec3n::new(-1.0, 0.5, 0.0));
+        assert_near(transform.get_local_scale(), Vec3n::all(0.5));
+        assert_near(transform.get_world_position(), Vec3n::new(3.0, 1.0, 0.0));
+        assert_near(transform.get_world_scale(), Vec3n::all(1.0));
+        assert_near(
+            transform.get_world_rotation() * Vec3n::right(),
+            Vec3n::right(),
+        );
+    }
+
+    #[test]
+    fn stale_handles_do_not_resolve_after_slot_reuse() {
+        let (container, [root, _, _, leg]) = build_scene();
+        container
+            .borrow_mut()
+            .remove_node(leg, RemovalMode::Subtree)
+            .unwrap();
+
+        let tail = SceneNode::create_new(container.clone(), Some("tail"), Some(root));
+        let scene = container.borrow();
+        assert_eq!(tail.get_index(), leg.get_index());
+        assert_ne!(tail.get_generation(), leg.get_generation());
+        assert!(scene.get_node(leg).is_none());
+        assert!(scene.has_node(tail));
+        assert_eq!(scene.find_by_name("leg"), None);
+        assert_eq!(scene.find_by_name("tail"), Some(tail));
+        assert!(scene.validate().is_empty());
+    }
+
+    #[test]
+    fn traversals_visit_in_order() {
+        let (container, [_, arm, hand, _]) = build_scene();
+        let scene = container.borrow();
+        let expect = |names: &[(&str, usize)]| -> Vec<(String, usize)> {
+            names.iter().map(|(n, d)| (n.to_string(), *d)).collect()
+        };
+
+        assert_eq!(
+            get_names(&scene, scene.iter_pre_order()),
+            expect(&[("root", 0), ("arm", 1), ("hand", 2), ("leg", 1)])
+        );
+        assert_eq!(
+            get_names(&scene, scene.iter_post_order()),
+            expect(&[("hand", 2), ("arm", 1), ("leg", 1), ("root", 0)])
+        );
+        assert_eq!(
+            get_names(&scene, scene.iter_breadth_first()),
+            expect(&[("root", 0), ("arm", 1), ("leg", 1), ("hand", 2)])
+        );
+        assert_eq!(
+            get_names(&scene, scene.iter_ancestors(hand)),
+            expect(&[("arm", 1), ("root", 2)])
+        );
+        assert_eq!(
+            get_names(&scene, scene.iter_descendants(arm)),
+            expect(&[("hand", 1)])
+        );
+    }
+
+    #[test]
+    fn paths_resolve_relative_to_the_start() {
+        let (container, [root, arm, hand, leg]) = build_scene();
+        let scene = container.borrow();
+
+        assert_eq!(scene.find_by_path(None, "root/arm/hand"), Some(hand));
+        assert_eq!(scene.find_by_path(Some(root), "./leg/"), Some(leg));
+        assert_eq!(scene.find_by_path(Some(hand), ".."), Some(arm));
+        assert_eq!(scene.find_by_path(Some(hand), "../../leg"), Some(leg));
+        assert_eq!(scene.find_by_path(None, "root/missing"), None);
+        assert_eq!(scene.find_by_path(None, "arm"), None);
+        //Stepping above a root lands on the roots themselves, one more step has nowhere to go
+        assert_eq!(scene.find_by_path(Some(root), "../root"), Some(root));
+        assert_eq!(scene.find_by_path(None, ".."), None);
+    }
+
+    #[test]
+    fn raycast_picks_mesh_renderers_through_the_container_collision_mesh() {
+        let (container, [_, arm, hand, leg]) = build_scene();
+        let mut scene = container.borrow_mut();
+        let renderer = |mesh: &str| MeshRenderer {
+            mesh: mesh.to_string(),
+            shader: "default".to_string(),
+            material: None,
+        };
+        //A square in the z = 0 plane
+        let quad = Rc::new(CollisionMesh::new(
+            vec![
+                Vec3n::new(-1.0, -1.0, 0.0),
+                Vec3n::new(1.0, -1.0, 0.0),
+                Vec3n::new(1.0, 1.0, 0.0),
+                Vec3n::new(-1.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+        ));
+        scene.set_collision_mesh("quad.obj", quad);
+        for (node_id, z) in [(hand, -2.0), (leg, -5.0)].iter() {
+            scene.add_component(*node_id, renderer("quad.obj")).unwrap();
+            let node = scene.get_node(*node_id).unwrap();
+            (*node)
+                .borrow_mut()
+                .get_transform()
+                .set_local_position(Vec3n::new(0.0, 0.0, *z));
+        }
+        scene.add_component(arm, renderer("missing.obj")).unwrap();
+        assert_eq!(scene.get_ids_without_collider(), vec![arm]);
+
+        let ray = Ray::new(Vec3n::new(0.5, 0.25, 5.0), Vec3n::new(0.0, 0.0, -1.0));
+        let hit = scene.raycast(&ray).unwrap();
+        assert_eq!(hit.node, hand);
+        assert!((hit.distance - 7.0).abs() < 1e-5);
+        assert_near(hit.position, Vec3n::new(0.5, 0.25, -2.0));
+
+        //A MeshCollider replaces the container's mesh, this one is out of the ray's way
+        let aside = CollisionMesh::new(
+            vec![
+                Vec3n::new(5.0, 5.0, 0.0),
+                Vec3n::new(6.0, 5.0, 0.0),
+                Vec3n::new(5.0, 6.0, 0.0),
+            ],
+            vec![[0, 1, 2]],
+        );
+        scene
+            .add_component(
+                hand,
+                MeshCollider {
+                    mesh: Rc::new(aside),
+                },
+            )
+            .unwrap();
+        assert_eq!(scene.raycast(&ray).unwrap().node, leg);
     }
 }
@@ -0,0 +1,303 @@
+use super::components::MeshRenderer;
+use super::node_handle::NodeHandle;
+use super::scene_container::SceneContainer;
+use super::scene_node::SceneNode;
+use core::fmt;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use straal::{Quatn, Vec3n};
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(PathBuf, std::io::Error),
+    Serialize(ron::ser::Error),
+    Parse(PathBuf, ron::de::Error),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneFileError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            SceneFileError::Serialize(err) => write!(f, "Could not serialize scene: {}", err),
+            SceneFileError::Parse(path, err) => {
+                write!(f, "{}: not a valid scene file: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+//The on disk layout of a scene, children are nested inside their parent so the file reads like the hierarchy
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SceneFile {
+    nodes: Vec<NodeEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NodeEntry {
+    name: String,
+    transform: TransformEntry,
+    #[serde(default)]
+    mesh: Option<MeshRenderer>,
+    #[serde(default)]
+    children: Vec<NodeEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TransformEntry {
+    position: (f32, f32, f32),
+    //Quaternion as (w, x, y, z)
+    rotation: (f32, f32, f32, f32),
+    scale: (f32, f32, f32),
+}
+
+impl NodeEntry {
+    fn from_node(container: &SceneContainer, node: &mut SceneNode) -> NodeEntry {
+        let transform = node.get_transform();
+        let position = transform.get_local_position();
+        let rotation = transform.get_local_rotation();
+        let scale = transform.get_local_scale();
+        NodeEntry {
+            name: node.get_name().to_string(),
+            transform: TransformEntry {
+                position: (position.x, position.y, position.z),
+                rotation: (rotation.w, rotation.x, rotation.y, rotation.z),
+                scale: (scale.x, scale.y, scale.z),
+            },
+            mesh: container
+                .get_component::<MeshRenderer>(node.get_id())
+                .cloned(),
+            children: node
+                .get_children_ids_unchecked()
+                .into_iter()
+                .flat_map(|id| container.get_node(id))
+                .map(|child| NodeEntry::from_node(container, &mut (*child).borrow_mut()))
+                .collect(),
+        }
+    }
+
+    fn create_in(&self, container: &Rc<RefCell<SceneContainer>>, parent: Option<NodeHandle>) {
+        let node_id = SceneNode::create_new(container.clone(), Some(&self.name), parent);
+        let node = (*container).borrow().get_node(node_id).unwrap();
+        {
+            let mut node = (*node).borrow_mut();
+            let (px, py, pz) = self.transform.position;
+            let (w, x, y, z) = self.transform.rotation;
+            let (sx, sy, sz) = self.transform.scale;
+            let transform = node.get_transform();
+            transform.set_local_position(Vec3n::new(px, py, pz));
+            transform.set_local_rotation(Quatn { w, x, y, z });
+            transform.set_local_scale(Vec3n::new(sx, sy, sz));
+        }
+        if let Some(mesh) = self.mesh.clone() {
+            (*container)
+                .borrow_mut()
+                .add_component(node_id, mesh)
+                .unwrap();
+        }
+        for child in self.children.iter() {
+            child.create_in(container, Some(node_id));
+        }
+    }
+}
+
+impl SceneContainer {
+    //Writes every node with its hierarchy, local transform and MeshRenderer component as RON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneFileError> {
+        let scene = SceneFile {
+            nodes: self
+                .get_root_ids()
+                .into_iter()
+                .flat_map(|id| self.get_node(id))
+                .map(|node| NodeEntry::from_node(self, &mut (*node).borrow_mut()))
+                .collect(),
+        };
+        let text = ron::ser::to_string_pretty(&scene, PrettyConfig::default())
+            .map_err(SceneFileError::Serialize)?;
+        fs::write(path.as_ref(), text)
+            .map_err(|err| SceneFileError::Io(path.as_ref().to_path_buf(), err))
+    }
+
+    //Builds a new container from a file written by save, node handles are not preserved
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Rc<RefCell<SceneContainer>>, SceneFileError> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).map_err(|err| SceneFileError::Io(path.to_path_buf(), err))?;
+        let scene: SceneFile = ron::de::from_str(&text)
+            .map_err(|err| SceneFileError::Parse(path.to_path_buf(), err))?;
+
+        let container = SceneContainer::new();
+        for node in scene.nodes.iter() {
+            node.create_in(&container, None);
+        }
+        Ok(container)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::scene_map::scene_container::RemovalMode;
+
+    //Everything a scene file is expected to keep, per node in pre-order
+    #[derive(Debug)]
+    struct NodeSnapshot {
+        name: String,
+        parent: Option<String>,
+        depth: usize,
+        values: [f32; 10],
+        mesh: Option<MeshRenderer>,
+    }
+
+    fn get_snapshot(container: &SceneContainer) -> Vec<NodeSnapshot> {
+        container
+            .iter_pre_order()
+            .map(|(id, depth)| {
+                let node = container.get_node(id).unwrap();
+                let mut node = (*node).borrow_mut();
+                let parent = node
+                    .get_parent_id()
+                    .and_then(|parent_id| container.get_node(parent_id))
+                    .map(|parent| {
+                        let parent = (*parent).borrow();
+                        parent.get_name().to_string()
+                    });
+                let transform = node.get_transform();
+                let p = transform.get_local_position();
+                let r = transform.get_local_rotation();
+                let s = transform.get_local_scale();
+                NodeSnapshot {
+                    name: node.get_name().to_string(),
+                    parent,
+                    depth,
+                    values: [p.x, p.y, p.z, r.w, r.x, r.y, r.z, s.x, s.y, s.z],
+                    mesh: container.get_component::<MeshRenderer>(id).cloned(),
+                }
+            })
+            .collect()
+    }
+
+    fn assert_same_scene(expected: &SceneContainer, actual: &SceneContainer) {
+        let expected = get_snapshot(expected);
+        let actual = get_snapshot(actual);
+        assert_eq!(expected.len(), actual.len());
+        for (a, b) in expected.iter().zip(actual.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.parent, b.parent, "parent of {}", a.name);
+            assert_eq!(a.depth, b.depth, "depth of {}", a.name);
+            assert_eq!(a.mesh, b.mesh, "mesh of {}", a.name);
+            for (x, y) in a.values.iter().zip(b.values.iter()) {
+                assert!((x - y).abs() < 1e-6, "transform of {} differs", a.name);
+            }
+        }
+    }
+
+    fn get_temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wolfsbane_{}_{}.ron", name, std::process::id()))
+    }
+
+    fn round_trip(container: &SceneContainer, name: &str) -> Rc<RefCell<SceneContainer>> {
+        let path = get_temp_path(name);
+        container.save(&path).unwrap();
+        let loaded = SceneContainer::load(&path);
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap()
+    }
+
+    fn get_renderer(mesh: &str, shader: &str) -> Option<MeshRenderer> {
+        Some(MeshRenderer {
+            mesh: mesh.to_string(),
+            shader: shader.to_string(),
+            material: None,
+        })
+    }
+
+    #[test]
+    fn showcase_loads_the_expected_hierarchy() {
+        let container = SceneContainer::load("res/scenes/showcase.ron").unwrap();
+        let snapshot = get_snapshot(&container.borrow());
+        let pedestal = Some("pedestal".to_string());
+        let expected = vec![
+            (
+                "pedestal",
+                None,
+                get_renderer("res/meshes/quad.obj", "gouraud"),
+            ),
+            (
+                "bunny",
+                pedestal.clone(),
+                get_renderer("res/meshes/bunny.obj", "normals"),
+            ),
+            (
+                "teapot",
+                pedestal,
+                get_renderer("res/meshes/teapot_smooth.obj", "gouraud"),
+            ),
+        ];
+
+        assert_eq!(snapshot.len(), expected.len());
+        for (node, (name, parent, mesh)) in snapshot.iter().zip(expected.into_iter()) {
+            assert_eq!(node.name, name);
+            assert_eq!(node.parent, parent, "parent of {}", name);
+            assert_eq!(node.mesh, mesh, "mesh of {}", name);
+        }
+    }
+
+    #[test]
+    fn showcase_survives_a_round_trip() {
+        let original = SceneContainer::load("res/scenes/showcase.ron").unwrap();
+        let original = original.borrow();
+        assert_eq!(original.node_count(), 3);
+        for (_, mesh) in original.query::<MeshRenderer>() {
+            assert!(Path::new(&mesh.mesh).is_file(), "{} is missing", mesh.mesh);
+        }
+
+        let loaded = round_trip(&original, "showcase");
+        assert_same_scene(&original, &loaded.borrow());
+    }
+
+    #[test]
+    fn built_hierarchy_survives_a_round_trip() {
+        let container = SceneContainer::new();
+        let robot = SceneNode::create_new(container.clone(), Some("robot"), None);
+        let arm = SceneNode::create_new(container.clone(), Some("arm"), Some(robot));
+        let hand = SceneNode::create_new(container.clone(), Some("hand"), Some(arm));
+        let removed = SceneNode::create_new(container.clone(), Some("removed"), Some(robot));
+        SceneNode::create_new(container.clone(), Some("lamp"), None);
+        {
+            let mut scene = container.borrow_mut();
+            scene.remove_node(removed, RemovalMode::Subtree).unwrap();
+            let hand = scene.get_node(hand).unwrap();
+            let mut hand = (*hand).borrow_mut();
+            let transform = hand.get_transform();
+            transform.set_local_position(Vec3n::new(0.5, -2.0, 3.25));
+            transform.set_local_rotation(Quatn {
+                w: 0.5,
+                x: 0.5,
+                y: -0.5,
+                z: 0.5,
+            });
+            transform.set_local_scale(Vec3n::new(2.0, 1.0, 0.5));
+            scene
+                .add_component(
+                    arm,
+                    MeshRenderer {
+                        mesh: "res/meshes/cone_flat_shaded.obj".to_string(),
+                        shader: "phong".to_string(),
+                        material: Some("metal".to_string()),
+                    },
+                )
+                .unwrap();
+        }
+
+        let loaded = round_trip(&container.borrow(), "built");
+        let loaded = loaded.borrow();
+        assert_same_scene(&container.borrow(), &loaded);
+        assert!(loaded.find_by_path(None, "robot/arm/hand").is_some());
+        assert_eq!(loaded.find_by_name("removed"), None);
+    }
+}
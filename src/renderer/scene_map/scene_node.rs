@@ -1,18 +1,19 @@
+use super::node_handle::NodeHandle;
 use super::scene_container::SceneContainer;
 use crate::renderer::Transform;
 use core::borrow::BorrowMut;
 use core::fmt;
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::string::ToString;
+use straal::{Mat4n, Quatn, Vec3n};
 
 pub struct SceneNode {
     transform: Transform<f32>,
     container: Rc<RefCell<SceneContainer>>,
-    parent: Option<u64>,
-    children: Vec<u64>,
-    id: u64,
+    parent: Option<NodeHandle>,
+    children: Vec<NodeHandle>,
+    id: NodeHandle,
     name: String,
 }
 
@@ -20,15 +21,15 @@ impl SceneNode {
     pub fn create_new(
         container: Rc<RefCell<SceneContainer>>,
         name: Option<&str>,
-        parent: Option<u64>,
-    ) -> u64 {
+        parent: Option<NodeHandle>,
+    ) -> NodeHandle {
         let mut container_deref = (*container).borrow_mut();
         let new_node_id = container_deref.add_node(Rc::new(RefCell::new(SceneNode {
             transform: Transform::default(),
             container: container.clone(),
-            parent,
+            parent: None,
             children: Vec::new(),
-            id: 0,
+            id: NodeHandle::INVALID,
             name: if name.is_some() {
                 name.unwrap().to_string()
             } else {
@@ -36,17 +37,14 @@ impl SceneNode {
             },
         })));
 
-        if parent.is_some() {
-            let parent_id = parent.unwrap();
-            let parent_node = container_deref.get_node(parent_id).unwrap();
-            let mut parent_node = (*parent_node).borrow_mut();
-            parent_node.add_child_id(new_node_id);
+        if let Some(parent_id) = parent {
+            container_deref.link(new_node_id, parent_id);
         }
 
         new_node_id
     }
 
-    pub(in crate::renderer::scene_map) fn set_id(&mut self, id: u64) {
+    pub(in crate::renderer::scene_map) fn set_id(&mut self, id: NodeHandle) {
         self.id = id;
     }
 
@@ -54,7 +52,7 @@ impl SceneNode {
         self.transform.borrow_mut()
     }
 
-    pub fn get_id(&self) -> u64 {
+    pub fn get_id(&self) -> NodeHandle {
         self.id
     }
 
@@ -62,27 +60,21 @@ impl SceneNode {
         self.name.as_str()
     }
 
-    pub fn add_child_id(&mut self, node_id: u64) {
-        if (*self.container).borrow_mut().has_node(node_id) {
-            match self.children.iter().find(|node| **node == node_id) {
-                None => self.children.push(node_id),
-                Some(_) => (),
-            }
-        }
+    //Only changes the node itself, use SceneContainer::rename_node to keep name lookups current
+    pub(in crate::renderer::scene_map) fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
     }
 
-    pub fn add_child(&mut self, child_node: Rc<RefCell<SceneNode>>) {
-        self.add_child_id((*child_node).borrow().get_id());
+    //Only touches this side of the link, use SceneContainer::reparent to keep both sides consistent
+    pub(in crate::renderer::scene_map) fn add_child_id(&mut self, node_id: NodeHandle) {
+        if !self.children.contains(&node_id) {
+            self.children.push(node_id);
+        }
     }
 
-    pub fn remove_child(&mut self, node_id: u64) {
-        let position = self.children.iter().position(|node| *node == node_id);
-        match position {
-            None => (),
-            Some(index) => {
-                self.children.remove(index);
-            }
-        }
+    //Only touches this side of the link, use SceneContainer::reparent to keep both sides consistent
+    pub(in crate::renderer::scene_map) fn remove_child_id(&mut self, node_id: NodeHandle) {
+        self.children.retain(|child| *child != node_id);
     }
 
     pub fn get_child_count(&self) -> usize {
@@ -92,42 +84,97 @@ impl SceneNode {
     //Gets a child from a given index, if the child was not available, it returns None
     pub fn get_child(&self, index: usize) -> Option<Rc<RefCell<SceneNode>>> {
         (*self.container)
-            .borrow_mut()
-            .get_node(self.children[index])
+            .borrow()
+            .get_node(*self.children.get(index)?)
     }
 
-    //Returns a list of child id's guaraneed to be available for usage from the data structure
-    pub fn get_children_ids(&mut self) -> Vec<u64> {
+    //Returns a list of child id's available for usage from the data structure
+    pub fn get_children_ids(&mut self) -> Vec<NodeHandle> {
         self.children
             .iter()
-            .take_while(|c| (*self.container).borrow().has_node(**c))
+            .filter(|c| (*self.container).borrow().has_node(**c))
             .cloned()
             .collect()
     }
 
-    //Returns a list of children guaranteed to not be deleted from the data structure
+    //Returns the child id's as stored, without consulting the container
+    pub(in crate::renderer::scene_map) fn get_children_ids_unchecked(&self) -> Vec<NodeHandle> {
+        self.children.clone()
+    }
+
+    //Returns a list of children that are still present in the data structure
     pub fn get_children(&self) -> Vec<Rc<RefCell<SceneNode>>> {
         self.children
             .iter()
-            .take_while(|c| (*self.container).borrow().has_node(**c))
-            .flat_map(|c| (*self.container).borrow_mut().get_node(*c))
+            .flat_map(|c| (*self.container).borrow().get_node(*c))
             .collect()
     }
 
     pub fn get_parent(&self) -> Option<Rc<RefCell<SceneNode>>> {
         match self.parent {
             None => None,
-            Some(parent_id) => (*self.container).borrow_mut().get_node(parent_id),
+            Some(parent_id) => (*self.container).borrow().get_node(parent_id),
         }
     }
 
-    pub fn set_parent_id(&mut self, parent_id: u64) {
-        if (*self.container).borrow_mut().has_node(parent_id) {
-            self.parent = Some(parent_id);
+    //Only touches this side of the link, use SceneContainer::reparent to keep both sides consistent
+    pub(in crate::renderer::scene_map) fn set_parent_id(&mut self, parent_id: Option<NodeHandle>) {
+        self.parent = parent_id;
+    }
+
+    //Walks up the parent chain and refreshes every cached world transform that went stale on the way down,
+    //the chain above this node is handled by SceneContainer::update_world_transform
+    pub fn update_world_transform(&mut self) {
+        let parent = match self.parent {
+            None => None,
+            Some(parent_id) => {
+                let container = (*self.container).borrow();
+                container.update_world_transform(parent_id);
+                container.get_node(parent_id)
+            }
+        };
+        self.place_in_parent(parent);
+    }
+
+    //Puts this node's transform in the world space of its parent, which has to be up to date already
+    pub(in crate::renderer::scene_map) fn place_in_parent(
+        &mut self,
+        parent: Option<Rc<RefCell<SceneNode>>>,
+    ) {
+        match parent {
+            None => self.transform.clear_parent(),
+            Some(parent) => {
+                let mut parent = (*parent).borrow_mut();
+                self.transform.set_parent(parent.get_transform());
+            }
         }
     }
 
-    pub fn get_parent_id(&self) -> Option<u64> {
+    pub fn get_local_to_world_matrix(&mut self) -> Mat4n {
+        self.update_world_transform();
+        self.transform.get_local_to_world_matrix()
+    }
+
+    pub fn get_world_to_local_matrix(&mut self) -> Mat4n {
+        self.get_local_to_world_matrix().inverse()
+    }
+
+    pub fn get_world_position(&mut self) -> Vec3n {
+        self.update_world_transform();
+        self.transform.get_world_position()
+    }
+
+    pub fn get_world_rotation(&mut self) -> Quatn {
+        self.update_world_transform();
+        self.transform.get_world_rotation()
+    }
+
+    pub fn get_world_scale(&mut self) -> Vec3n {
+        self.update_world_transform();
+        self.transform.get_world_scale()
+    }
+
+    pub fn get_parent_id(&self) -> Option<NodeHandle> {
         self.parent
     }
 }
@@ -141,3 +188,115 @@ impl fmt::Debug for SceneNode {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use straal::Vec4n;
+
+    fn assert_near(a: Vec3n, b: Vec3n) {
+        let close =
+            (a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4 && (a.z - b.z).abs() < 1e-4;
+        assert!(close, "{:?} is not close to {:?}", a, b);
+    }
+
+    //root, turned half way around y and scaled up, with child and grandchild below it
+    fn build_chain() -> (Rc<RefCell<SceneContainer>>, [Rc<RefCell<SceneNode>>; 3]) {
+        let container = SceneContainer::new();
+        let root = SceneNode::create_new(container.clone(), Some("root"), None);
+        let child = SceneNode::create_new(container.clone(), Some("child"), Some(root));
+        let grandchild = SceneNode::create_new(container.clone(), Some("grandchild"), Some(child));
+        let nodes = {
+            let scene = container.borrow();
+            [
+                scene.get_node(root).unwrap(),
+                scene.get_node(child).unwrap(),
+                scene.get_node(grandchild).unwrap(),
+            ]
+        };
+        {
+            let mut root = (*nodes[0]).borrow_mut();
+            let transform = root.get_transform();
+            transform.set_local_position(Vec3n::new(1.0, 0.0, 0.0));
+            transform.set_local_rotation(Quatn::get_quat_from_angle_axis(
+                std::f32::consts::PI,
+                Vec3n::up(),
+            ));
+            transform.set_local_scale(Vec3n::all(2.0));
+        }
+        {
+            let mut child = (*nodes[1]).borrow_mut();
+            let transform = child.get_transform();
+            transform.set_local_position(Vec3n::new(0.0, 0.0, 1.0));
+            transform.set_local_scale(Vec3n::all(0.5));
+        }
+        (*nodes[2])
+            .borrow_mut()
+            .get_transform()
+            .set_local_position(Vec3n::new(1.0, 0.0, 0.0));
+        (container, nodes)
+    }
+
+    #[test]
+    fn world_transform_composes_through_the_parent_chain() {
+        let (_container, [_, child, grandchild]) = build_chain();
+
+        let mut child = (*child).borrow_mut();
+        assert_near(child.get_world_position(), Vec3n::new(1.0, 0.0, -2.0));
+        assert_near(child.get_world_scale(), Vec3n::all(1.0));
+
+        let mut grandchild = (*grandchild).borrow_mut();
+        assert_near(grandchild.get_world_position(), Vec3n::new(0.0, 0.0, -2.0));
+        assert_near(grandchild.get_world_scale(), Vec3n::all(1.0));
+        assert_near(
+            grandchild.get_world_rotation() * Vec3n::right(),
+            Vec3n::new(-1.0, 0.0, 0.0),
+        );
+        let origin = grandchild.get_local_to_world_matrix() * Vec4n::new(0.0, 0.0, 0.0, 1.0);
+        assert_near(
+            Vec3n::new(origin.x, origin.y, origin.z),
+            Vec3n::new(0.0, 0.0, -2.0),
+        );
+    }
+
+    #[test]
+    fn cached_world_transform_follows_ancestor_changes() {
+        let (_container, [root, _, grandchild]) = build_chain();
+        assert_near(
+            (*grandchild).borrow_mut().get_world_position(),
+            Vec3n::new(0.0, 0.0, -2.0),
+        );
+
+        (*root)
+            .borrow_mut()
+            .get_transform()
+            .set_local_position(Vec3n::new(5.0, 0.0, 0.0));
+        assert_near(
+            (*grandchild).borrow_mut().get_world_position(),
+            Vec3n::new(4.0, 0.0, -2.0),
+        );
+
+        (*root)
+            .borrow_mut()
+            .get_transform()
+            .set_local_scale(Vec3n::all(1.0));
+        assert_near(
+            (*grandchild).borrow_mut().get_world_position(),
+            Vec3n::new(4.5, 0.0, -1.0),
+        );
+        assert_near(
+            (*grandchild).borrow_mut().get_world_scale(),
+            Vec3n::all(0.5),
+        );
+    }
+
+    #[test]
+    fn world_transform_works_while_the_container_is_borrowed() {
+        let (container, [_, child, _]) = build_chain();
+        let _scene = container.borrow();
+        assert_near(
+            (*child).borrow_mut().get_world_position(),
+            Vec3n::new(1.0, 0.0, -2.0),
+        );
+    }
+}
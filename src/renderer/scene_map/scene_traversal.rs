@@ -0,0 +1,146 @@
+use super::node_handle::NodeHandle;
+use super::scene_container::SceneContainer;
+use std::collections::VecDeque;
+
+//All traversals yield the handle of a node together with its depth,
+//which is counted from the node the traversal started at
+
+fn get_children(container: &SceneContainer, node_id: NodeHandle) -> Vec<NodeHandle> {
+    match container.get_node(node_id) {
+        None => Vec::new(),
+        Some(node) => (*node).borrow().get_children_ids_unchecked(),
+    }
+}
+
+//Visits a node before any of its children
+pub struct PreOrderIter<'a> {
+    container: &'a SceneContainer,
+    stack: Vec<(NodeHandle, usize)>,
+}
+
+impl<'a> PreOrderIter<'a> {
+    pub(in crate::renderer::scene_map) fn new(
+        container: &'a SceneContainer,
+        start: Vec<NodeHandle>,
+        depth: usize,
+    ) -> PreOrderIter<'a> {
+        PreOrderIter {
+            container,
+            stack: start.into_iter().rev().map(|id| (id, depth)).collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for PreOrderIter<'a> {
+    type Item = (NodeHandle, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node_id, depth) = self.stack.pop()?;
+        let children = get_children(self.container, node_id);
+        self.stack
+            .extend(children.into_iter().rev().map(|id| (id, depth + 1)));
+        Some((node_id, depth))
+    }
+}
+
+//Visits a node after all of its children, useful when children have to be handled before their parent
+pub struct PostOrderIter<'a> {
+    container: &'a SceneContainer,
+    stack: Vec<(NodeHandle, usize, bool)>,
+}
+
+impl<'a> PostOrderIter<'a> {
+    pub(in crate::renderer::scene_map) fn new(
+        container: &'a SceneContainer,
+        start: Vec<NodeHandle>,
+        depth: usize,
+    ) -> PostOrderIter<'a> {
+        PostOrderIter {
+            container,
+            stack: start
+                .into_iter()
+                .rev()
+                .map(|id| (id, depth, false))
+                .collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for PostOrderIter<'a> {
+    type Item = (NodeHandle, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node_id, depth, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some((node_id, depth));
+            }
+            self.stack.push((node_id, depth, true));
+            let children = get_children(self.container, node_id);
+            self.stack
+                .extend(children.into_iter().rev().map(|id| (id, depth + 1, false)));
+        }
+    }
+}
+
+//Visits every node of a depth before moving on to the next depth
+pub struct BreadthFirstIter<'a> {
+    container: &'a SceneContainer,
+    queue: VecDeque<(NodeHandle, usize)>,
+}
+
+impl<'a> BreadthFirstIter<'a> {
+    pub(in crate::renderer::scene_map) fn new(
+        container: &'a SceneContainer,
+        start: Vec<NodeHandle>,
+        depth: usize,
+    ) -> BreadthFirstIter<'a> {
+        BreadthFirstIter {
+            container,
+            queue: start.into_iter().map(|id| (id, depth)).collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for BreadthFirstIter<'a> {
+    type Item = (NodeHandle, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (node_id, depth) = self.queue.pop_front()?;
+        let children = get_children(self.container, node_id);
+        self.queue
+            .extend(children.into_iter().map(|id| (id, depth + 1)));
+        Some((node_id, depth))
+    }
+}
+
+//Walks up from a node towards its root, the depth here is the distance to the starting node
+pub struct AncestorIter<'a> {
+    container: &'a SceneContainer,
+    current: Option<NodeHandle>,
+    depth: usize,
+}
+
+impl<'a> AncestorIter<'a> {
+    pub(in crate::renderer::scene_map) fn new(
+        container: &'a SceneContainer,
+        node_id: NodeHandle,
+    ) -> AncestorIter<'a> {
+        AncestorIter {
+            container,
+            current: Some(node_id),
+            depth: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for AncestorIter<'a> {
+    type Item = (NodeHandle, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.container.get_node(self.current?)?;
+        self.current = (*node).borrow().get_parent_id();
+        self.depth += 1;
+        Some((self.current?, self.depth))
+    }
+}
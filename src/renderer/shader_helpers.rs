@@ -1,9 +1,175 @@
+use super::shader_reflection::ShaderReflection;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
 use std::io::BufReader;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+//Root that #include "..." paths are resolved against
+pub const SHADER_ROOT: &str = "res/shaders/";
+
+//Include files available to the built-in shaders, which can not rely on res/ being next to the executable
+const BUILTIN_INCLUDES: &[(&str, &str)] = &[
+    ("common/transforms.glsl", include_str!("../../res/shaders/common/transforms.glsl")),
+    ("common/cotangent_frame.glsl", include_str!("../../res/shaders/common/cotangent_frame.glsl")),
+    ("common/lighting.glsl", include_str!("../../res/shaders/common/lighting.glsl")),
+    ("common/point_shadows.glsl", include_str!("../../res/shaders/common/point_shadows.glsl")),
+    ("common/cascaded_shadows.glsl", include_str!("../../res/shaders/common/cascaded_shadows.glsl")),
+];
+
+const TRIVIAL_VERTEX: &str = "#version 150\nvoid main() { gl_Position = vec4(0.0); }";
+const TRIVIAL_FRAGMENT: &str = "#version 150\nout vec4 color;\nvoid main() { color = vec4(1.0); }";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Geometry,
+}
+
+//A single message from a driver compile log, with the source line it points at when the driver reported one.
+//For preprocessed sources the file and line are mapped back to where the line was written.
+#[derive(Debug, Clone)]
+pub struct ShaderDiagnostic {
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ShaderDiagnostic {
+    pub fn parse_log(log: &str) -> Vec<ShaderDiagnostic> {
+        log.lines()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| ShaderDiagnostic { file: None, line: ShaderDiagnostic::parse_line_number(l), message: l.to_string() })
+            .collect()
+    }
+
+    //Drivers disagree on the format, this handles "0:12(5): error" (Mesa), "ERROR: 0:12:" (AMD) and "0(12) : error" (NVIDIA)
+    fn parse_line_number(log_line: &str) -> Option<usize> {
+        let bytes = log_line.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if !bytes[i].is_ascii_digit() {
+                i += 1;
+                continue;
+            }
+            let mut j = i;
+            while j < bytes.len() && bytes[j].is_ascii_digit() {
+                j += 1;
+            }
+            if j < bytes.len() && (bytes[j] == b'(' || bytes[j] == b':') {
+                let start = j + 1;
+                let mut end = start;
+                while end < bytes.len() && bytes[end].is_ascii_digit() {
+                    end += 1;
+                }
+                if end > start {
+                    return log_line[start..end].parse().ok();
+                }
+            }
+            i = j;
+        }
+        None
+    }
+}
+
+//Output of the preprocessor, remembering for every line of code which file and line it came from
+#[derive(Debug, Clone)]
+pub struct PreprocessedSource {
+    pub code: String,
+    origins: Vec<(usize, usize)>,
+    files: Vec<PathBuf>,
+}
+
+impl PreprocessedSource {
+    //Every file that went into this source, the top level one first
+    pub fn get_files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    //File and line a line of the preprocessed code was written at, both counted from 1
+    pub fn get_origin(&self, line: usize) -> Option<(&Path, usize)> {
+        let (file, original_line) = *self.origins.get(line.checked_sub(1)?)?;
+        Some((self.files[file].as_path(), original_line))
+    }
+
+    fn map_diagnostic(&self, diagnostic: ShaderDiagnostic) -> ShaderDiagnostic {
+        match diagnostic.line.and_then(|line| self.get_origin(line)) {
+            Some((file, line)) => ShaderDiagnostic { file: Some(file.to_path_buf()), line: Some(line), message: diagnostic.message },
+            None => diagnostic,
+        }
+    }
+
+    fn push_line(&mut self, line: &str, file: usize, original_line: usize) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.origins.push((file, original_line));
+    }
+
+    fn file_index(&mut self, path: &Path) -> usize {
+        match self.files.iter().position(|f| f == path) {
+            Some(index) => index,
+            None => {
+                self.files.push(path.to_path_buf());
+                self.files.len() - 1
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    MissingFolder(PathBuf),
+    Io { path: PathBuf, error: std::io::Error },
+    //The chain of files that include each other, ending with the file that closes the loop
+    IncludeCycle(Vec<PathBuf>),
+    Preprocess { path: PathBuf, line: usize, message: String },
+    //The stage is None when the failing stage could not be isolated
+    Compilation { stage: Option<ShaderStage>, diagnostics: Vec<ShaderDiagnostic>, log: String },
+    Linking(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShaderError::MissingFolder(path) => write!(f, "There is no shader folder at {}", path.display()),
+            ShaderError::Io { path, error } => write!(f, "Could not read {}: {}", path.display(), error),
+            ShaderError::IncludeCycle(chain) => {
+                let chain: Vec<String> = chain.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "Include cycle: {}", chain.join(" -> "))
+            }
+            ShaderError::Preprocess { path, line, message } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ShaderError::Compilation { stage, diagnostics, log } => {
+                match stage {
+                    Some(stage) => writeln!(f, "{:?} shader failed to compile:", stage)?,
+                    None => writeln!(f, "Shader failed to compile:")?,
+                }
+                if diagnostics.iter().any(|d| d.file.is_some()) {
+                    for diagnostic in diagnostics.iter() {
+                        match (&diagnostic.file, diagnostic.line) {
+                            (Some(file), Some(line)) => writeln!(f, "{}:{}: {}", file.display(), line, diagnostic.message)?,
+                            _ => writeln!(f, "{}", diagnostic.message)?,
+                        }
+                    }
+                    Ok(())
+                } else {
+                    write!(f, "{}", log)
+                }
+            }
+            ShaderError::Linking(log) => write!(f, "Shader program failed to link:\n{}", log),
+            ShaderError::Unsupported(msg) => write!(f, "Shader program is not supported: {}", msg),
+        }
+    }
+}
+
+impl Error for ShaderError {}
+
+#[derive(Clone, Copy)]
 pub struct Shader {
+    name: &'static str,
     vertex: &'static str,
     fragment: &'static str,
     geometry: Option<&'static str>,
@@ -12,6 +178,7 @@ pub struct Shader {
 impl Shader {
     #[allow(dead_code)]
     pub const PHONG: Self = Self {
+        name: "phong",
         vertex: include_str!("../../res/shaders/phong/phong.vert"),
         fragment: include_str!("../../res/shaders/phong/phong.frag"),
         geometry: None,
@@ -19,6 +186,7 @@ impl Shader {
 
     #[allow(dead_code)]
     pub const GOURAUD: Self = Self {
+        name: "gouraud",
         vertex: include_str!("../../res/shaders/gouraud/gouraud.vert"),
         fragment: include_str!("../../res/shaders/gouraud/gouraud.frag"),
         geometry: None,
@@ -26,6 +194,7 @@ impl Shader {
 
     #[allow(dead_code)]
     pub const COLORED2D: Self = Self {
+        name: "colored2d",
         vertex: include_str!("../../res/shaders/colored2d/colored2d.vert"),
         fragment: include_str!("../../res/shaders/colored2d/colored2d.frag"),
         geometry: None,
@@ -33,30 +202,106 @@ impl Shader {
 
     #[allow(dead_code)]
     pub const NORMALS: Self = Self {
+        name: "normals",
         vertex: include_str!("../../res/shaders/normals/normals.vert"),
         fragment: include_str!("../../res/shaders/normals/normals.frag"),
         geometry: None,
     };
 
+    //Faceted shading from per-face normals made in a geometry stage, so it works on any mesh regardless of its normals
+    #[allow(dead_code)]
+    pub const FLAT: Self = Self {
+        name: "flat",
+        vertex: include_str!("../../res/shaders/flat/flat.vert"),
+        fragment: include_str!("../../res/shaders/flat/flat.frag"),
+        geometry: Some(include_str!("../../res/shaders/flat/flat.geom")),
+    };
 
+    //Metallic-roughness physically based shading, see Material::pbr for the parameters it takes
     #[allow(dead_code)]
-    pub fn load(display: &glium::Display, shader_type: Self) -> Option<glium::Program> {
-        Shader::compile_shader(display, shader_type.vertex, shader_type.fragment, shader_type.geometry)
+    pub const PBR: Self = Self {
+        name: "pbr",
+        vertex: include_str!("../../res/shaders/pbr/pbr.vert"),
+        fragment: include_str!("../../res/shaders/pbr/pbr.frag"),
+        geometry: None,
+    };
+
+    //Writes the distance to a point light, used to render point light shadow maps
+    #[allow(dead_code)]
+    pub const POINT_SHADOW: Self = Self {
+        name: "point_shadow",
+        vertex: include_str!("../../res/shaders/point_shadow/point_shadow.vert"),
+        fragment: include_str!("../../res/shaders/point_shadow/point_shadow.frag"),
+        geometry: None,
+    };
+
+    //Writes light space depth, used to render the cascades of directional light shadows
+    #[allow(dead_code)]
+    pub const DIRECTIONAL_SHADOW: Self = Self {
+        name: "directional_shadow",
+        vertex: include_str!("../../res/shaders/directional_shadow/directional_shadow.vert"),
+        fragment: include_str!("../../res/shaders/directional_shadow/directional_shadow.frag"),
+        geometry: None,
+    };
+
+
+    pub fn get_name(&self) -> &'static str {
+        self.name
+    }
+
+    //Lists the uniforms and vertex attributes a compiled program reads
+    pub fn reflect(program: &glium::Program) -> ShaderReflection {
+        ShaderReflection::new(program)
+    }
+
+    #[allow(dead_code)]
+    pub fn load(display: &glium::Display, shader_type: Self) -> Result<glium::Program, ShaderError> {
+        Shader::load_with_defines(display, shader_type, &[])
+    }
+
+    //Loads a built-in shader with extra #defines injected right after the #version line of every stage
+    pub fn load_with_defines(display: &glium::Display, shader_type: Self, defines: &[(&str, &str)]) -> Result<glium::Program, ShaderError> {
+        let (vert, frag, geom) = shader_type.read_builtin(defines)?;
+        Shader::compile_preprocessed(display, &vert, &frag, geom.as_ref())
     }
 
     #[allow(dead_code)]
-    pub fn load_dynamic(display: &glium::Display, shader_name: &str) -> Option<glium::Program> {
+    pub fn load_dynamic(display: &glium::Display, shader_name: &str) -> Result<glium::Program, ShaderError> {
+        Shader::load_dynamic_with_defines(display, shader_name, &[])
+    }
+
+    //Loads res/shaders/<name>/ with extra #defines injected right after the #version line of every stage
+    pub fn load_dynamic_with_defines(display: &glium::Display, shader_name: &str, defines: &[(&str, &str)]) -> Result<glium::Program, ShaderError> {
+        let (vert, frag, geom) = Shader::read_dynamic(shader_name, defines)?;
+        Shader::compile_preprocessed(display, &vert, &frag, geom.as_ref())
+    }
+
+    //Every file a dynamic shader is built from, including the ones pulled in through #include
+    pub(crate) fn dynamic_dependencies(shader_name: &str) -> Result<Vec<PathBuf>, ShaderError> {
+        let (vert, frag, geom) = Shader::read_dynamic(shader_name, &[])?;
+        let mut files: Vec<PathBuf> = Vec::new();
+        for source in [Some(&vert), Some(&frag), geom.as_ref()].iter().flatten() {
+            for file in source.get_files() {
+                if !files.contains(file) {
+                    files.push(file.clone());
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    //Locations of the vertex, fragment and optional geometry source of a shader in res/shaders/<name>/
+    pub(crate) fn dynamic_source_paths(shader_name: &str) -> Result<(String, String, Option<String>), ShaderError> {
         let folder_location = format!("res/shaders/{}/", shader_name);
         if !Shader::path_exists(folder_location.as_str()) {
-            println!("There is no folder for {}", shader_name);
-            None
+            Err(ShaderError::MissingFolder(PathBuf::from(folder_location)))
         } else {
             let shader_location = format!("{}{}", folder_location, shader_name);
             let vert_shader_path = format!("{}.vert", shader_location);
             let frag_shader_path = format!("{}.frag", shader_location);
             let geom_shader_path = format!("{}.geom", shader_location);
-            let geom_shader = if Shader::path_exists(geom_shader_path.as_str()) { Some(geom_shader_path.as_str()) } else { None };
-            Shader::read_shader(display, vert_shader_path.as_str(), frag_shader_path.as_str(), geom_shader)
+            let geom_shader = if Shader::path_exists(geom_shader_path.as_str()) { Some(geom_shader_path) } else { None };
+            Ok((vert_shader_path, frag_shader_path, geom_shader))
         }
     }
 
@@ -65,60 +310,326 @@ impl Shader {
         filepath.exists()
     }
 
-    fn read_shader(display: &glium::Display, vert_path: &str, frag_path: &str, geom_path: Option<&str>) -> Option<glium::Program> {
-        let vert_source = match Shader::read_file(vert_path) {
-            Ok(source) => source,
-            Err(e) => {
-                println!("Error reading vertex shader source file:\n{:?}", e);
-                return None;
-            }
+    //Built-in stages are reported as <name>.vert, <name>.frag and <name>.geom, like the files they were embedded from
+    fn read_builtin(&self, defines: &[(&str, &str)]) -> Result<(PreprocessedSource, PreprocessedSource, Option<PreprocessedSource>), ShaderError> {
+        let read_stage = |extension: &str, source: &str| {
+            let path = PathBuf::from(format!("{}.{}", self.name, extension));
+            Shader::preprocess(&path, source, defines, &Shader::read_builtin_include)
         };
-        let frag_source = match Shader::read_file(frag_path) {
-            Ok(source) => source,
-            Err(e) => {
-                println!("Error reading fragment shader source file:\n{:?}", e);
-                return None;
-            }
+        let vert = read_stage("vert", self.vertex)?;
+        let frag = read_stage("frag", self.fragment)?;
+        let geom = match self.geometry {
+            Some(source) => Some(read_stage("geom", source)?),
+            None => None,
         };
+        Ok((vert, frag, geom))
+    }
 
-        let mut geom_src = String::new();
-        let geom_source = if geom_path.is_none() {
-            None
-        } else {
-            match Shader::read_file(geom_path.unwrap()) {
-                Ok(source) => {
-                    geom_src = source;
-                    Some(geom_src.as_str())
+    fn read_dynamic(shader_name: &str, defines: &[(&str, &str)]) -> Result<(PreprocessedSource, PreprocessedSource, Option<PreprocessedSource>), ShaderError> {
+        let (vert_path, frag_path, geom_path) = Shader::dynamic_source_paths(shader_name)?;
+        let read_stage = |path: &str| -> Result<PreprocessedSource, ShaderError> {
+            let source = Shader::read_file(path)?;
+            Shader::preprocess(Path::new(path), &source, defines, &Shader::read_include)
+        };
+        let vert = read_stage(vert_path.as_str())?;
+        let frag = read_stage(frag_path.as_str())?;
+        let geom = match geom_path {
+            Some(path) => Some(read_stage(path.as_str())?),
+            None => None,
+        };
+        Ok((vert, frag, geom))
+    }
+
+    //Resolves #include "..." directives against SHADER_ROOT and injects the defines after the #version line.
+    //The path is only used to name the source in errors and to detect include cycles.
+    pub fn preprocess<F>(path: &Path, source: &str, defines: &[(&str, &str)], read_include: &F) -> Result<PreprocessedSource, ShaderError>
+        where F: Fn(&Path) -> Result<String, ShaderError> {
+        let mut output = PreprocessedSource { code: String::new(), origins: Vec::new(), files: Vec::new() };
+        let mut include_stack = Vec::new();
+        Shader::preprocess_into(path, source, Some(defines), read_include, &mut include_stack, &mut output)?;
+        Ok(output)
+    }
+
+    fn preprocess_into<F>(path: &Path, source: &str, defines: Option<&[(&str, &str)]>, read_include: &F, include_stack: &mut Vec<PathBuf>, output: &mut PreprocessedSource) -> Result<(), ShaderError>
+        where F: Fn(&Path) -> Result<String, ShaderError> {
+        if include_stack.iter().any(|p| p == path) {
+            let mut chain = include_stack.clone();
+            chain.push(path.to_path_buf());
+            return Err(ShaderError::IncludeCycle(chain));
+        }
+        include_stack.push(path.to_path_buf());
+        let file = output.file_index(path);
+
+        //Defines have to follow #version, which has to come first, without one they simply go on top
+        let mut pending_defines = defines;
+        if !source.lines().any(|l| l.trim_start().starts_with("#version")) {
+            if let Some(defines) = pending_defines.take() {
+                for (name, value) in defines.iter() {
+                    output.push_line(&format!("#define {} {}", name, value), file, 1);
                 }
-                Err(e) => {
-                    println!("Error reading geometry shader source file:\n{:?}", e);
-                    return None;
+            }
+        }
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("#include") {
+                let include_name = match (trimmed.find('"'), trimmed.rfind('"')) {
+                    (Some(start), Some(end)) if end > start + 1 => &trimmed[start + 1..end],
+                    _ => return Err(ShaderError::Preprocess {
+                        path: path.to_path_buf(),
+                        line: line_number,
+                        message: format!("Expected a quoted path after #include, found {}", trimmed),
+                    }),
+                };
+                let include_path = Path::new(SHADER_ROOT).join(include_name);
+                let include_source = read_include(&include_path)?;
+                Shader::preprocess_into(&include_path, &include_source, None, read_include, include_stack, output)?;
+            } else {
+                output.push_line(line, file, line_number);
+                if trimmed.starts_with("#version") {
+                    if let Some(defines) = pending_defines.take() {
+                        for (name, value) in defines.iter() {
+                            output.push_line(&format!("#define {} {}", name, value), file, line_number);
+                        }
+                    }
                 }
             }
-        };
-        Shader::compile_shader(display, &vert_source, &frag_source, geom_source)
+        }
+
+        include_stack.pop();
+        Ok(())
+    }
+
+    fn read_include(path: &Path) -> Result<String, ShaderError> {
+        match path.to_str() {
+            Some(path) => Shader::read_file(path),
+            None => Err(ShaderError::Io {
+                path: path.to_path_buf(),
+                error: std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path is not valid unicode"),
+            }),
+        }
+    }
+
+    fn read_builtin_include(path: &Path) -> Result<String, ShaderError> {
+        let name = path.strip_prefix(SHADER_ROOT).unwrap_or(path);
+        match BUILTIN_INCLUDES.iter().find(|(include, _)| Path::new(include) == name) {
+            Some((_, source)) => Ok(source.to_string()),
+            None => Err(ShaderError::Io {
+                path: path.to_path_buf(),
+                error: std::io::Error::new(std::io::ErrorKind::NotFound, "Not one of the built-in includes"),
+            }),
+        }
+    }
+
+    //Compiles preprocessed stages, mapping the line numbers of compile errors back to the original files
+    fn compile_preprocessed(display: &glium::Display, vert: &PreprocessedSource, frag: &PreprocessedSource, geom: Option<&PreprocessedSource>) -> Result<glium::Program, ShaderError> {
+        let result = Shader::compile_shader(display, &vert.code, &frag.code, geom.map(|g| g.code.as_str()));
+        result.map_err(|err| match err {
+            ShaderError::Compilation { stage: Some(stage), diagnostics, log } => {
+                let source = match stage {
+                    ShaderStage::Vertex => Some(vert),
+                    ShaderStage::Fragment => Some(frag),
+                    ShaderStage::Geometry => geom,
+                };
+                let diagnostics = match source {
+                    Some(source) => diagnostics.into_iter().map(|d| source.map_diagnostic(d)).collect(),
+                    None => diagnostics,
+                };
+                ShaderError::Compilation { stage: Some(stage), diagnostics, log }
+            }
+            err => err,
+        })
     }
 
-    fn compile_shader(display: &glium::Display, vert_src: &str, frag_src: &str, geom_src: Option<&str>) -> Option<glium::Program> {
+    fn compile_shader(display: &glium::Display, vert_src: &str, frag_src: &str, geom_src: Option<&str>) -> Result<glium::Program, ShaderError> {
         let result = glium::Program::from_source(display, vert_src, frag_src, geom_src);
         match result {
-            Ok(r) => Some(r),
-            Err(err) => {
-                match err {
-                    glium::CompilationError(msg) => println!("{:?}", msg),
-                    glium::LinkingError(msg) => println!("{:?}", msg),
-                    _ => println!("{:?}", err)
-                }
-                None
+            Ok(r) => Ok(r),
+            Err(err) => Err(match err {
+                glium::CompilationError(log) => ShaderError::Compilation {
+                    stage: Shader::find_failing_stage(display, vert_src, frag_src, geom_src),
+                    diagnostics: ShaderDiagnostic::parse_log(&log),
+                    log,
+                },
+                glium::LinkingError(log) => ShaderError::Linking(log),
+                _ => ShaderError::Unsupported(format!("{:?}", err))
+            })
+        }
+    }
+
+    //The driver log does not say which stage it belongs to, so when compilation fails every stage
+    //is compiled again next to trivial counterparts until the one that still fails is found
+    fn find_failing_stage(display: &glium::Display, vert_src: &str, frag_src: &str, geom_src: Option<&str>) -> Option<ShaderStage> {
+        let fails = |vert: &str, frag: &str, geom: Option<&str>| match glium::Program::from_source(display, vert, frag, geom) {
+            Err(glium::CompilationError(_)) => true,
+            _ => false,
+        };
+        if fails(vert_src, TRIVIAL_FRAGMENT, None) {
+            Some(ShaderStage::Vertex)
+        } else if fails(TRIVIAL_VERTEX, frag_src, None) {
+            Some(ShaderStage::Fragment)
+        } else if geom_src.is_some() && fails(TRIVIAL_VERTEX, TRIVIAL_FRAGMENT, geom_src) {
+            Some(ShaderStage::Geometry)
+        } else {
+            None
+        }
+    }
+
+    fn read_file(file_path: &str) -> Result<String, ShaderError> {
+        let read = || -> std::io::Result<String> {
+            let file = File::open(file_path)?;
+            let mut buf_reader = BufReader::new(file);
+            let mut contents = String::new();
+            buf_reader.read_to_string(&mut contents)?;
+            Ok(contents)
+        };
+        read().map_err(|error| ShaderError::Io { path: PathBuf::from(file_path), error })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn get_path(name: &str) -> PathBuf {
+        Path::new(SHADER_ROOT).join(name)
+    }
+
+    //Preprocesses the first file, includes are looked up among the others
+    fn preprocess(files: &[(&str, &str)], defines: &[(&str, &str)]) -> Result<PreprocessedSource, ShaderError> {
+        let sources: HashMap<PathBuf, String> = files.iter().map(|(name, source)| (get_path(name), source.to_string())).collect();
+        let read_include = |path: &Path| match sources.get(path) {
+            Some(source) => Ok(source.clone()),
+            None => Err(ShaderError::Io {
+                path: path.to_path_buf(),
+                error: std::io::Error::new(std::io::ErrorKind::NotFound, "Not in the test sources"),
+            }),
+        };
+        let (name, source) = files[0];
+        Shader::preprocess(&get_path(name), source, defines, &read_include)
+    }
+
+    fn get_lines(log: &str) -> Vec<Option<usize>> {
+        ShaderDiagnostic::parse_log(log).iter().map(|d| d.line).collect()
+    }
+
+    #[test]
+    fn parses_mesa_logs() {
+        let log = "0:12(5): error: `foo' undeclared\n0:3(10): warning: unused variable";
+        assert_eq!(get_lines(log), vec![Some(12), Some(3)]);
+    }
+
+    #[test]
+    fn parses_amd_logs() {
+        let log = "ERROR: 0:27: 'normal' : undeclared identifier\nERROR: 1 compilation errors.  No code generated.";
+        assert_eq!(get_lines(log), vec![Some(27), None]);
+    }
+
+    #[test]
+    fn parses_nvidia_logs() {
+        let log = "0(41) : error C1008: undefined variable \"light\"\n\n0(7) : warning C7050: \"c\" might be used before being initialized";
+        assert_eq!(get_lines(log), vec![Some(41), Some(7)]);
+    }
+
+    #[test]
+    fn keeps_messages_without_a_line() {
+        let diagnostics = ShaderDiagnostic::parse_log("  Link failed because of missing stages  ");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, None);
+        assert_eq!(diagnostics[0].message, "Link failed because of missing stages");
+    }
+
+    #[test]
+    fn injects_defines_after_version() {
+        let source = "//Header comment\n#version 330\nvoid main() {}";
+        let output = preprocess(&[("main.frag", source)], &[("HAS_UV", "1"), ("LIGHTS", "4")]).unwrap();
+        assert_eq!(output.code, "//Header comment\n#version 330\n#define HAS_UV 1\n#define LIGHTS 4\nvoid main() {}\n");
+        assert_eq!(output.get_origin(3), Some((get_path("main.frag").as_path(), 2)));
+        assert_eq!(output.get_origin(5), Some((get_path("main.frag").as_path(), 3)));
+    }
+
+    #[test]
+    fn puts_defines_on_top_without_version() {
+        let output = preprocess(&[("main.frag", "void main() {}")], &[("HAS_UV", "1")]).unwrap();
+        assert_eq!(output.code, "#define HAS_UV 1\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn defines_do_not_leak_into_includes() {
+        let files = [
+            ("main.frag", "#version 330\n#include \"common/a.glsl\""),
+            ("common/a.glsl", "#version 330\nfloat a;"),
+        ];
+        let output = preprocess(&files, &[("HAS_UV", "1")]).unwrap();
+        assert_eq!(output.code.matches("#define HAS_UV 1").count(), 1);
+    }
+
+    #[test]
+    fn maps_lines_back_to_included_files() {
+        let files = [
+            ("main.frag", "#version 330\n#include \"common/a.glsl\"\nvoid main() {}"),
+            ("common/a.glsl", "float a;\n#include \"common/b.glsl\"\nfloat c;"),
+            ("common/b.glsl", "float b;"),
+        ];
+        let output = preprocess(&files, &[]).unwrap();
+        assert_eq!(output.code, "#version 330\nfloat a;\nfloat b;\nfloat c;\nvoid main() {}\n");
+        assert_eq!(output.get_files(), &[get_path("main.frag"), get_path("common/a.glsl"), get_path("common/b.glsl")][..]);
+        assert_eq!(output.get_origin(0), None);
+        assert_eq!(output.get_origin(1), Some((get_path("main.frag").as_path(), 1)));
+        assert_eq!(output.get_origin(3), Some((get_path("common/b.glsl").as_path(), 1)));
+        assert_eq!(output.get_origin(4), Some((get_path("common/a.glsl").as_path(), 3)));
+        assert_eq!(output.get_origin(5), Some((get_path("main.frag").as_path(), 3)));
+        assert_eq!(output.get_origin(6), None);
+
+        let diagnostic = ShaderDiagnostic::parse_log("0:4(1): error: syntax error").remove(0);
+        let diagnostic = output.map_diagnostic(diagnostic);
+        assert_eq!(diagnostic.file, Some(get_path("common/a.glsl")));
+        assert_eq!(diagnostic.line, Some(3));
+    }
+
+    #[test]
+    fn names_builtin_stages_after_the_shader() {
+        let (vert, frag, geom) = Shader::FLAT.read_builtin(&[]).unwrap();
+        assert_eq!(vert.get_origin(1).unwrap().0, Path::new("flat.vert"));
+        assert_eq!(frag.get_origin(1).unwrap().0, Path::new("flat.frag"));
+        assert_eq!(geom.unwrap().get_origin(1).unwrap().0, Path::new("flat.geom"));
+    }
+
+    #[test]
+    fn detects_include_cycles() {
+        let files = [
+            ("main.frag", "#include \"common/a.glsl\""),
+            ("common/a.glsl", "#include \"common/b.glsl\""),
+            ("common/b.glsl", "#include \"common/a.glsl\""),
+        ];
+        match preprocess(&files, &[]) {
+            Err(ShaderError::IncludeCycle(chain)) => {
+                assert_eq!(chain, vec![get_path("main.frag"), get_path("common/a.glsl"), get_path("common/b.glsl"), get_path("common/a.glsl")]);
             }
+            other => panic!("Expected an include cycle, got {:?}", other),
         }
     }
 
-    fn read_file(file_path: &str) -> std::io::Result<String> {
-        let file = File::open(file_path)?;
-        let mut buf_reader = BufReader::new(file);
-        let mut contents = String::new();
-        buf_reader.read_to_string(&mut contents)?;
-        Ok(contents)
+    #[test]
+    fn allows_including_a_file_twice_without_a_cycle() {
+        let files = [
+            ("main.frag", "#include \"common/a.glsl\"\n#include \"common/a.glsl\""),
+            ("common/a.glsl", "float a;"),
+        ];
+        let output = preprocess(&files, &[]).unwrap();
+        assert_eq!(output.code, "float a;\nfloat a;\n");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn reports_malformed_includes() {
+        match preprocess(&[("main.frag", "#version 330\n#include common/a.glsl")], &[]) {
+            Err(ShaderError::Preprocess { path, line, .. }) => {
+                assert_eq!(path, get_path("main.frag"));
+                assert_eq!(line, 2);
+            }
+            other => panic!("Expected a preprocess error, got {:?}", other),
+        }
+    }
+}
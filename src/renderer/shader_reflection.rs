@@ -0,0 +1,273 @@
+use std::fmt;
+
+use glium::uniforms::{UniformType, Uniforms};
+use glium::vertex::{AttributeType, VertexFormat};
+
+#[derive(Debug, Clone)]
+pub struct UniformInfo {
+    pub name: String,
+    pub ty: UniformType,
+    //None for uniforms that are not arrays
+    pub array_size: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub ty: AttributeType,
+    pub array_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum ReflectionError {
+    MissingAttribute {
+        name: String,
+        expected: AttributeType,
+    },
+    AttributeMismatch {
+        name: String,
+        expected: AttributeType,
+        found: AttributeType,
+    },
+    MissingUniform {
+        name: String,
+        expected: UniformType,
+    },
+    UniformMismatch {
+        name: String,
+        expected: UniformType,
+    },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReflectionError::MissingAttribute { name, expected } => write!(
+                f,
+                "The shader reads vertex attribute {} ({:?}), but the mesh does not provide it",
+                name, expected
+            ),
+            ReflectionError::AttributeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "The shader reads vertex attribute {} as {:?}, but the mesh provides {:?}",
+                name, expected, found
+            ),
+            ReflectionError::MissingUniform { name, expected } => write!(
+                f,
+                "The shader uses uniform {} ({:?}), but nothing provides it",
+                name, expected
+            ),
+            ReflectionError::UniformMismatch { name, expected } => write!(
+                f,
+                "Uniform {} is provided, but can not be used as the {:?} the shader expects",
+                name, expected
+            ),
+        }
+    }
+}
+
+//What a compiled program reads, as reported by the driver.
+//Only active inputs are listed, anything the compiler optimized away does not show up.
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    uniforms: Vec<UniformInfo>,
+    attributes: Vec<AttributeInfo>,
+}
+
+impl ShaderReflection {
+    pub fn new(program: &glium::Program) -> ShaderReflection {
+        let uniforms: Vec<UniformInfo> = program
+            .uniforms()
+            .map(|(name, uniform)| UniformInfo {
+                name: name.clone(),
+                ty: uniform.ty,
+                array_size: uniform.size,
+            })
+            .collect();
+
+        //Built-ins like gl_VertexID are fed by GL itself
+        let attributes: Vec<AttributeInfo> = program
+            .attributes()
+            .filter(|(name, _)| !name.starts_with("gl_"))
+            .map(|(name, attribute)| AttributeInfo {
+                name: name.clone(),
+                ty: attribute.ty,
+                array_size: attribute.size,
+            })
+            .collect();
+
+        ShaderReflection::from_info(uniforms, attributes)
+    }
+
+    //For inputs that are known without asking the driver, they do not have to be sorted
+    pub fn from_info(
+        mut uniforms: Vec<UniformInfo>,
+        mut attributes: Vec<AttributeInfo>,
+    ) -> ShaderReflection {
+        uniforms.sort_by(|a, b| a.name.cmp(&b.name));
+        attributes.sort_by(|a, b| a.name.cmp(&b.name));
+        ShaderReflection {
+            uniforms,
+            attributes,
+        }
+    }
+
+    pub fn get_uniforms(&self) -> &[UniformInfo] {
+        &self.uniforms
+    }
+
+    pub fn get_uniform(&self, name: &str) -> Option<&UniformInfo> {
+        self.uniforms.iter().find(|u| u.name == name)
+    }
+
+    pub fn get_attributes(&self) -> &[AttributeInfo] {
+        &self.attributes
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&AttributeInfo> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    //Checks that the vertex format of a mesh provides every attribute the shader reads, with a matching type
+    pub fn validate_vertex_format(&self, format: &VertexFormat) -> Vec<ReflectionError> {
+        self.attributes
+            .iter()
+            .filter_map(|attribute| {
+                let provided = format
+                    .iter()
+                    .find(|(name, _, _, _)| *name == attribute.name);
+                match provided {
+                    None => Some(ReflectionError::MissingAttribute {
+                        name: attribute.name.clone(),
+                        expected: attribute.ty,
+                    }),
+                    Some((_, _, ty, _)) if *ty != attribute.ty => {
+                        Some(ReflectionError::AttributeMismatch {
+                            name: attribute.name.clone(),
+                            expected: attribute.ty,
+                            found: *ty,
+                        })
+                    }
+                    Some(_) => None,
+                }
+            })
+            .collect()
+    }
+
+    //Checks that the given uniforms, e.g. the result of uniform! or a material, cover every uniform the shader uses
+    pub fn validate_uniforms<U: Uniforms>(&self, uniforms: &U) -> Vec<ReflectionError> {
+        let mut errors = Vec::new();
+        let mut provided = Vec::new();
+        uniforms.visit_values(|name, value| {
+            if let Some(uniform) = self.get_uniform(name) {
+                if !value.is_usable_with(&uniform.ty) {
+                    errors.push(ReflectionError::UniformMismatch {
+                        name: name.to_string(),
+                        expected: uniform.ty,
+                    });
+                }
+            }
+            provided.push(name.to_string());
+        });
+
+        for uniform in self.uniforms.iter() {
+            //Array elements are reported as name[0], they are provided under either name
+            let base_name = uniform.name.trim_end_matches("[0]");
+            if !provided
+                .iter()
+                .any(|p| p == &uniform.name || p == base_name)
+            {
+                errors.push(ReflectionError::MissingUniform {
+                    name: uniform.name.clone(),
+                    expected: uniform.ty,
+                });
+            }
+        }
+        errors
+    }
+
+    //Both checks at once, for when a program is first drawn with a mesh and its uniforms
+    pub fn validate<U: Uniforms>(
+        &self,
+        format: &VertexFormat,
+        uniforms: &U,
+    ) -> Vec<ReflectionError> {
+        let mut errors = self.validate_vertex_format(format);
+        errors.extend(self.validate_uniforms(uniforms));
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+
+    //What the phong shader reads
+    fn get_phong() -> ShaderReflection {
+        let uniform = |name: &str, ty| UniformInfo {
+            name: name.to_string(),
+            ty,
+            array_size: None,
+        };
+        let attribute = |name: &str, ty| AttributeInfo {
+            name: name.to_string(),
+            ty,
+            array_size: 1,
+        };
+        ShaderReflection::from_info(
+            vec![
+                uniform("model", UniformType::FloatMat4),
+                uniform("base_color", UniformType::FloatVec4),
+            ],
+            vec![
+                attribute("position", AttributeType::F32F32F32),
+                attribute("tex_coords", AttributeType::F32F32),
+            ],
+        )
+    }
+
+    fn get_format(attributes: &[(&'static str, AttributeType)]) -> VertexFormat {
+        Cow::Owned(
+            attributes
+                .iter()
+                .map(|(name, ty)| (Cow::Borrowed(*name), 0, *ty, false))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn accepts_matching_inputs() {
+        let format = get_format(&[
+            ("position", AttributeType::F32F32F32),
+            ("normal", AttributeType::F32F32F32),
+            ("tex_coords", AttributeType::F32F32),
+        ]);
+        let uniforms = uniform! {model: [[1.0f32; 4]; 4], base_color: [1.0f32; 4], unused: 1.0f32};
+        assert!(get_phong().validate(&format, &uniforms).is_empty());
+    }
+
+    #[test]
+    fn reports_missing_and_mismatched_inputs() {
+        let format = get_format(&[("position", AttributeType::F32F32)]);
+        let uniforms = uniform! {model: 1.0f32};
+        let errors: Vec<String> = get_phong()
+            .validate(&format, &uniforms)
+            .iter()
+            .map(|err| err.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                "The shader reads vertex attribute position as F32F32F32, but the mesh provides F32F32",
+                "The shader reads vertex attribute tex_coords (F32F32), but the mesh does not provide it",
+                "Uniform model is provided, but can not be used as the FloatMat4 the shader expects",
+                "The shader uses uniform base_color (FloatVec4), but nothing provides it",
+            ]
+        );
+    }
+}
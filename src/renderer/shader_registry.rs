@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use super::shader_helpers::{Shader, ShaderError};
+
+//Modification times of every file a dynamic shader is built from, taken right before it was last compiled
+struct WatchedFiles {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl WatchedFiles {
+    fn new(paths: Vec<PathBuf>) -> WatchedFiles {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = WatchedFiles::get_modified(&path);
+                (path, modified)
+            })
+            .collect();
+        WatchedFiles { files }
+    }
+
+    fn get_modified(path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    //The same files with their current modification times
+    fn refresh(&self) -> WatchedFiles {
+        WatchedFiles::new(self.files.iter().map(|(path, _)| path.clone()).collect())
+    }
+
+    //The modification time taken when the file was last looked at, files that are new to the list are looked at now
+    fn get_known_modified(&self, path: &Path) -> Option<SystemTime> {
+        match self.files.iter().find(|(known, _)| known == path) {
+            Some((_, modified)) => *modified,
+            None => WatchedFiles::get_modified(path),
+        }
+    }
+
+    fn is_outdated(&self) -> bool {
+        self.files
+            .iter()
+            .any(|(path, modified)| WatchedFiles::get_modified(path) != *modified)
+    }
+}
+
+//A program built from files on disk that is recompiled once one of them changes, shared by ShaderRegistry
+//and ShaderVariantCache. Finding the files and compiling are left to the caller, so P is only a program outside of tests.
+pub(crate) struct WatchedProgram<P = glium::Program> {
+    program: P,
+    files: WatchedFiles,
+    last_error: Option<ShaderError>,
+}
+
+impl<P> WatchedProgram<P> {
+    //The first compile has to succeed, since there is no earlier program to fall back on
+    pub(crate) fn load<D, C>(discover: D, compile: C) -> Result<WatchedProgram<P>, ShaderError>
+    where
+        D: FnOnce() -> Result<Vec<PathBuf>, ShaderError>,
+        C: FnOnce() -> Result<P, ShaderError>,
+    {
+        //Timestamps are taken before compiling, so edits made while compiling trigger a reload
+        let files = WatchedFiles::new(discover()?);
+        Ok(WatchedProgram {
+            program: compile()?,
+            files,
+            last_error: None,
+        })
+    }
+
+    pub(crate) fn get_program(&self) -> &P {
+        &self.program
+    }
+
+    //The error of the latest failed reload, None once the program compiles again
+    pub(crate) fn get_error(&self) -> Option<&ShaderError> {
+        self.last_error.as_ref()
+    }
+
+    //Recompiles when a watched file changed since the last attempt and returns whether it did.
+    //The new timestamps are stored before anything is read, so a broken file is only tried again once it is edited.
+    pub(crate) fn reload_changed<D, C>(&mut self, discover: D, compile: C) -> bool
+    where
+        D: FnOnce() -> Result<Vec<PathBuf>, ShaderError>,
+        C: FnOnce() -> Result<P, ShaderError>,
+    {
+        if !self.files.is_outdated() {
+            return false;
+        }
+        self.files = self.files.refresh();
+        //Includes may have been added or removed, files known so far keep the timestamps just taken
+        match discover() {
+            Ok(paths) => {
+                let files = paths
+                    .into_iter()
+                    .map(|path| {
+                        let modified = self.files.get_known_modified(&path);
+                        (path, modified)
+                    })
+                    .collect();
+                self.files = WatchedFiles { files };
+            }
+            Err(err) => {
+                self.last_error = Some(err);
+                return true;
+            }
+        }
+        match compile() {
+            Ok(program) => {
+                self.program = program;
+                self.last_error = None;
+            }
+            Err(err) => self.last_error = Some(err),
+        }
+        true
+    }
+}
+
+//Keeps dynamically loaded shaders (res/shaders/<name>/) around and recompiles them when their sources,
+//or any file they #include, change on disk.
+//A shader that fails to recompile keeps its last working program, the error is kept around until the next successful compile.
+pub struct ShaderRegistry {
+    shaders: HashMap<String, WatchedProgram>,
+}
+
+impl Default for ShaderRegistry {
+    fn default() -> ShaderRegistry {
+        ShaderRegistry::new()
+    }
+}
+
+impl ShaderRegistry {
+    pub fn new() -> ShaderRegistry {
+        ShaderRegistry {
+            shaders: HashMap::new(),
+        }
+    }
+
+    //The first load has to succeed, since there is no earlier program to fall back on
+    pub fn load(&mut self, display: &glium::Display, shader_name: &str) -> Result<(), ShaderError> {
+        let shader = WatchedProgram::load(
+            || Shader::dynamic_dependencies(shader_name),
+            || Shader::load_dynamic(display, shader_name),
+        )?;
+        self.shaders.insert(shader_name.to_string(), shader);
+        Ok(())
+    }
+
+    pub fn contains(&self, shader_name: &str) -> bool {
+        self.shaders.contains_key(shader_name)
+    }
+
+    pub fn get(&self, shader_name: &str) -> Option<&glium::Program> {
+        Some(self.shaders.get(shader_name)?.get_program())
+    }
+
+    //The error of the latest failed reload, None once the shader compiles again
+    pub fn get_error(&self, shader_name: &str) -> Option<&ShaderError> {
+        self.shaders.get(shader_name)?.get_error()
+    }
+
+    //Recompiles every shader with a source file that changed since it was last compiled, meant to be called between frames.
+    //Returns the names of the shaders that were recompiled along with the outcome.
+    pub fn reload_changed(
+        &mut self,
+        display: &glium::Display,
+    ) -> Vec<(String, Result<(), &ShaderError>)> {
+        let mut reloaded = Vec::new();
+        for (name, shader) in self.shaders.iter_mut() {
+            let changed = shader.reload_changed(
+                || Shader::dynamic_dependencies(name),
+                || Shader::load_dynamic(display, name),
+            );
+            if changed {
+                reloaded.push(name.clone());
+            }
+        }
+
+        reloaded
+            .into_iter()
+            .map(|name| {
+                let result = match self.shaders[&name].get_error() {
+                    Some(err) => Err(err),
+                    None => Ok(()),
+                };
+                (name, result)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn get_temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wolfsbane_{}_{}.glsl", name, std::process::id()))
+    }
+
+    //Gives the file a modification time of its own instead of relying on the clock moving between writes
+    fn touch(path: &Path, seconds: u64) {
+        File::create(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds))
+            .unwrap();
+    }
+
+    fn broken() -> Result<u32, ShaderError> {
+        Err(ShaderError::Unsupported("broken".to_string()))
+    }
+
+    #[test]
+    fn failed_reload_waits_for_the_next_edit() {
+        let path = get_temp_path("failed_reload");
+        touch(&path, 1000);
+        let discover = || Ok(vec![path.clone()]);
+        let mut shader = WatchedProgram::load(discover, || Ok(1)).unwrap();
+        assert!(!shader.reload_changed(discover, || panic!("nothing changed")));
+
+        touch(&path, 2000);
+        assert!(shader.reload_changed(discover, broken));
+        assert!(shader.get_error().is_some());
+        assert_eq!(*shader.get_program(), 1);
+        assert!(!shader.reload_changed(discover, || panic!("retried without an edit")));
+
+        touch(&path, 3000);
+        assert!(shader.reload_changed(discover, || Ok(2)));
+        assert!(shader.get_error().is_none());
+        assert_eq!(*shader.get_program(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_discovery_waits_for_the_next_edit() {
+        let path = get_temp_path("failed_discovery");
+        touch(&path, 1000);
+        let mut shader = WatchedProgram::load(|| Ok(vec![path.clone()]), || Ok(1)).unwrap();
+
+        //Like a bad #include, which fails before anything is compiled
+        touch(&path, 2000);
+        let missing_include = || Err(ShaderError::MissingFolder(path.clone()));
+        assert!(shader.reload_changed(missing_include, || panic!("compiled a bad include")));
+        assert!(shader.get_error().is_some());
+        assert!(!shader.reload_changed(missing_include, || panic!("retried without an edit")));
+
+        touch(&path, 3000);
+        assert!(shader.reload_changed(|| Ok(vec![path.clone()]), || Ok(2)));
+        assert_eq!(*shader.get_program(), 2);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use super::shader_helpers::{Shader, ShaderError};
+use super::shader_registry::WatchedProgram;
+
+//Feature keys understood by the built-in shaders, each one is passed to the shader as a #define
+pub const HAS_UV: &str = "HAS_UV";
+pub const HAS_DIFFUSE_MAP: &str = "HAS_DIFFUSE_MAP";
+pub const HAS_NORMAL_MAP: &str = "HAS_NORMAL_MAP";
+pub const HAS_ALBEDO_MAP: &str = "HAS_ALBEDO_MAP";
+pub const HAS_METALLIC_MAP: &str = "HAS_METALLIC_MAP";
+pub const HAS_ROUGHNESS_MAP: &str = "HAS_ROUGHNESS_MAP";
+pub const HAS_OCCLUSION_MAP: &str = "HAS_OCCLUSION_MAP";
+pub const HAS_EMISSIVE_MAP: &str = "HAS_EMISSIVE_MAP";
+
+//Which shader a variant is built from, built-in shaders are keyed by name
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum VariantSource {
+    Builtin(&'static str),
+    Dynamic(String),
+}
+
+enum Variant {
+    Builtin(glium::Program),
+    //Only dynamic shaders are watched, built-in ones can not change while running
+    Dynamic(WatchedProgram),
+}
+
+impl Variant {
+    fn get_program(&self) -> &glium::Program {
+        match self {
+            Variant::Builtin(program) => program,
+            Variant::Dynamic(program) => program.get_program(),
+        }
+    }
+
+    fn get_error(&self) -> Option<&ShaderError> {
+        match self {
+            Variant::Builtin(_) => None,
+            Variant::Dynamic(program) => program.get_error(),
+        }
+    }
+}
+
+//Compiles every combination of shader and feature keys once, on first use, and hands out the same program afterwards.
+//The order of the feature keys does not matter, duplicates are ignored.
+//Variants of dynamic shaders are recompiled by reload_changed the same way ShaderRegistry does it.
+pub struct ShaderVariantCache {
+    programs: HashMap<(VariantSource, Vec<String>), Variant>,
+}
+
+impl Default for ShaderVariantCache {
+    fn default() -> ShaderVariantCache {
+        ShaderVariantCache::new()
+    }
+}
+
+impl ShaderVariantCache {
+    pub fn new() -> ShaderVariantCache {
+        ShaderVariantCache {
+            programs: HashMap::new(),
+        }
+    }
+
+    fn normalize_features(features: &[&str]) -> Vec<String> {
+        let mut features: Vec<String> = features.iter().map(|f| f.to_string()).collect();
+        features.sort();
+        features.dedup();
+        features
+    }
+
+    fn get_or_compile<F>(
+        &mut self,
+        source: VariantSource,
+        features: &[&str],
+        compile: F,
+    ) -> Result<&glium::Program, ShaderError>
+    where
+        F: FnOnce(&[(&str, &str)]) -> Result<glium::Program, ShaderError>,
+    {
+        let key = (source, ShaderVariantCache::normalize_features(features));
+        if !self.programs.contains_key(&key) {
+            let defines = ShaderVariantCache::get_defines(&key.1);
+            let variant = match &key.0 {
+                VariantSource::Builtin(_) => Variant::Builtin(compile(&defines)?),
+                VariantSource::Dynamic(name) => Variant::Dynamic(WatchedProgram::load(
+                    || Shader::dynamic_dependencies(name),
+                    || compile(&defines),
+                )?),
+            };
+            self.programs.insert(key.clone(), variant);
+        }
+        Ok(self.programs[&key].get_program())
+    }
+
+    fn get_defines(features: &[String]) -> Vec<(&str, &str)> {
+        features.iter().map(|f| (f.as_str(), "1")).collect()
+    }
+
+    pub fn get_builtin(
+        &mut self,
+        display: &glium::Display,
+        shader: Shader,
+        features: &[&str],
+    ) -> Result<&glium::Program, ShaderError> {
+        self.get_or_compile(
+            VariantSource::Builtin(shader.get_name()),
+            features,
+            |defines| Shader::load_with_defines(display, shader, defines),
+        )
+    }
+
+    pub fn get_dynamic(
+        &mut self,
+        display: &glium::Display,
+        shader_name: &str,
+        features: &[&str],
+    ) -> Result<&glium::Program, ShaderError> {
+        self.get_or_compile(
+            VariantSource::Dynamic(shader_name.to_string()),
+            features,
+            |defines| Shader::load_dynamic_with_defines(display, shader_name, defines),
+        )
+    }
+
+    //Recompiles every variant of a dynamic shader with a source file that changed since it was last compiled,
+    //meant to be called between frames. A variant that fails to compile keeps its previous program.
+    //Returns the shader names, with the variant's feature keys in brackets, along with the outcome.
+    pub fn reload_changed(
+        &mut self,
+        display: &glium::Display,
+    ) -> Vec<(String, Result<(), &ShaderError>)> {
+        let mut reloaded = Vec::new();
+        for (key, variant) in self.programs.iter_mut() {
+            let (name, program) = match (&key.0, variant) {
+                (VariantSource::Dynamic(name), Variant::Dynamic(program)) => (name, program),
+                _ => continue,
+            };
+            let defines = ShaderVariantCache::get_defines(&key.1);
+            let changed = program.reload_changed(
+                || Shader::dynamic_dependencies(name),
+                || Shader::load_dynamic_with_defines(display, name, &defines),
+            );
+            if changed {
+                reloaded.push(key.clone());
+            }
+        }
+
+        reloaded
+            .into_iter()
+            .map(|key| {
+                let result = match self.programs[&key].get_error() {
+                    Some(err) => Err(err),
+                    None => Ok(()),
+                };
+                let name = match &key.0 {
+                    VariantSource::Dynamic(name) => name.clone(),
+                    VariantSource::Builtin(name) => name.to_string(),
+                };
+                (format!("{} [{}]", name, key.1.join(", ")), result)
+            })
+            .collect()
+    }
+
+    //Number of variants compiled so far
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    //Drops every compiled variant, they will be compiled again when next requested
+    pub fn clear(&mut self) {
+        self.programs.clear();
+    }
+}
@@ -0,0 +1,537 @@
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::texture::{DepthFormat, MipmapsOption, Texture2dArray, UncompressedFloatFormat};
+use glium::uniforms::{
+    AsUniformValue, MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior,
+    SamplerWrapFunction, UniformValue, Uniforms,
+};
+use glium::Surface;
+use straal::{Mat4n, Vec2n, Vec3n, Vec4n};
+
+use crate::renderer::matrix_helpers::get_view_matrix;
+use crate::renderer::projection::Projection;
+use crate::renderer::scene_map::components::{DirectionalShadow, Light, LightKind};
+use crate::renderer::scene_map::node_handle::NodeHandle;
+use crate::renderer::scene_map::scene_container::SceneContainer;
+use crate::renderer::shader_helpers::{Shader, ShaderError};
+
+//Has to match MAX_CASCADES in res/shaders/common/cascaded_shadows.glsl
+pub const MAX_CASCADES: usize = 4;
+
+const CASCADE_MATRIX_NAMES: [&str; MAX_CASCADES] = [
+    "cascade_matrices[0]",
+    "cascade_matrices[1]",
+    "cascade_matrices[2]",
+    "cascade_matrices[3]",
+];
+
+//The shadow settings of a light if it casts cascaded shadows, shared with LightBlock::gather so both pick the same light.
+//Only the first one found in pre-order is used.
+pub(crate) fn get_directional_shadow(
+    container: &SceneContainer,
+    node_id: NodeHandle,
+    light: &Light,
+) -> Option<DirectionalShadow> {
+    match light.kind {
+        LightKind::Directional => container
+            .get_component::<DirectionalShadow>(node_id)
+            .cloned(),
+        _ => None,
+    }
+}
+
+//The view space distances the cascades cover, from the camera's near plane up to max_distance or its far plane
+fn get_shadow_range(projection: &Projection, shadow: &DirectionalShadow) -> (f32, f32) {
+    let far = match projection.get_far() {
+        Some(far) => far.min(shadow.max_distance),
+        None => shadow.max_distance,
+    };
+    (projection.get_near(), far)
+}
+
+//Splits [near, far] into cascades, mixing evenly spaced and logarithmic splits.
+//Returns the far end of every cascade.
+fn get_split_distances(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let fraction = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+//Corners of the part of the camera's view between two view space distances
+fn get_frustum_corners(
+    projection: &Projection,
+    aspect_ratio: f32,
+    start: f32,
+    end: f32,
+) -> Vec<Vec3n> {
+    let mut corners = Vec::with_capacity(8);
+    for distance in [start, end].iter() {
+        let half_height = match *projection {
+            Projection::Perspective { fov, .. } => (fov / 2.0).tan() * distance,
+            Projection::Orthographic { size, .. } => size,
+        };
+        let half_width = half_height * aspect_ratio;
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter() {
+            corners.push(Vec3n::new(x * half_width, y * half_height, *distance));
+        }
+    }
+    corners
+}
+
+fn transform_point(matrix: &Mat4n, point: Vec3n) -> Vec3n {
+    let p = *matrix * Vec4n::from((point, 1.0));
+    Vec3n::new(p.x, p.y, p.z)
+}
+
+struct CascadeMap {
+    depths: Texture2dArray,
+    depth: DepthRenderBuffer,
+    resolution: u32,
+    layers: u32,
+}
+
+impl CascadeMap {
+    fn new(display: &glium::Display, resolution: u32, layers: u32) -> CascadeMap {
+        CascadeMap {
+            depths: Texture2dArray::empty_with_format(
+                display,
+                UncompressedFloatFormat::F32,
+                MipmapsOption::NoMipmap,
+                resolution,
+                resolution,
+                layers,
+            )
+            .unwrap(),
+            depth: DepthRenderBuffer::new(display, DepthFormat::I24, resolution, resolution)
+                .unwrap(),
+            resolution,
+            layers,
+        }
+    }
+}
+
+struct Cascade {
+    light_view_projection: Mat4n,
+    split: f32,
+}
+
+//Everything a draw call of the shadow pass needs, handed to the closure that draws the scene
+pub struct CascadeShadowPass<'a> {
+    program: &'a glium::Program,
+    draw_parameters: &'a glium::DrawParameters<'static>,
+    light_view_projection: Mat4n,
+    cascade: usize,
+}
+
+impl<'a> CascadeShadowPass<'a> {
+    pub fn get_program(&self) -> &glium::Program {
+        self.program
+    }
+
+    pub fn get_draw_parameters(&self) -> &glium::DrawParameters<'static> {
+        self.draw_parameters
+    }
+
+    //Which cascade is being rendered, starting at the one closest to the camera
+    pub fn get_cascade(&self) -> usize {
+        self.cascade
+    }
+
+    pub fn get_uniforms(&self, model_matrix: Mat4n) -> CascadeShadowPassUniforms {
+        CascadeShadowPassUniforms {
+            model: model_matrix,
+            light_view_projection: self.light_view_projection,
+        }
+    }
+}
+
+pub struct CascadeShadowPassUniforms {
+    model: Mat4n,
+    light_view_projection: Mat4n,
+}
+
+impl Uniforms for CascadeShadowPassUniforms {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut output: F) {
+        output("model", self.model.as_uniform_value());
+        output(
+            "light_view_projection",
+            self.light_view_projection.as_uniform_value(),
+        );
+    }
+}
+
+//Shadows for the sun, the camera's view is split into cascades by distance and every cascade gets its own
+//orthographic shadow map, so nearby shadows stay sharp without the far ones needing a huge texture
+pub struct CascadedShadowMaps {
+    program: glium::Program,
+    draw_parameters: glium::DrawParameters<'static>,
+    map: Option<CascadeMap>,
+    //Bound while there is no shadow casting directional light
+    empty_map: Texture2dArray,
+    cascades: Vec<Cascade>,
+    settings: Option<DirectionalShadow>,
+    debug_view: bool,
+}
+
+impl CascadedShadowMaps {
+    pub fn new(display: &glium::Display) -> Result<CascadedShadowMaps, ShaderError> {
+        let empty_map = Texture2dArray::empty_with_format(
+            display,
+            UncompressedFloatFormat::F32,
+            MipmapsOption::NoMipmap,
+            1,
+            1,
+            1,
+        )
+        .unwrap();
+        SimpleFrameBuffer::new(display, empty_map.main_level().layer(0).unwrap())
+            .unwrap()
+            .clear_color(1.0, 1.0, 1.0, 1.0);
+
+        Ok(CascadedShadowMaps {
+            program: Shader::load(display, Shader::DIRECTIONAL_SHADOW)?,
+            draw_parameters: glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::draw_parameters::DepthTest::IfLess,
+                    write: true,
+                    ..Default::default()
+                },
+                //Back faces only, which keeps lit surfaces from shadowing themselves
+                backface_culling: glium::draw_parameters::BackfaceCullingMode::CullClockwise,
+                ..Default::default()
+            },
+            map: None,
+            empty_map,
+            cascades: Vec::new(),
+            settings: None,
+            debug_view: false,
+        })
+    }
+
+    //Tints every cascade with its own colour in the lit shaders
+    pub fn set_debug_view(&mut self, debug_view: bool) {
+        self.debug_view = debug_view;
+    }
+
+    pub fn is_debug_view(&self) -> bool {
+        self.debug_view
+    }
+
+    pub fn get_cascade_count(&self) -> usize {
+        self.cascades.len()
+    }
+
+    //Fits the cascades around the view of the camera with the given projection and view matrix and renders them.
+    //The closure draws every shadow casting mesh into the given surface, with the pass's program, draw parameters
+    //and the uniforms it gives for the mesh's model matrix.
+    pub fn render<F>(
+        &mut self,
+        display: &glium::Display,
+        container: &SceneContainer,
+        projection: &Projection,
+        view_matrix: &Mat4n,
+        viewport: &Vec2n,
+        mut draw_scene: F,
+    ) where
+        F: FnMut(&mut SimpleFrameBuffer, &CascadeShadowPass),
+    {
+        let mut caster: Option<(Vec3n, DirectionalShadow)> = None;
+        container.visit_with_world(|node_id, _, node, parent_world| {
+            if caster.is_some() {
+                return;
+            }
+            if let Some(light) = container.get_component::<Light>(node_id) {
+                if let Some(shadow) = get_directional_shadow(container, node_id, light) {
+                    let world = *parent_world * node.get_transform().get_local_matrix();
+                    let direction = world * Vec4n::from((Vec3n::forward(), 0.0));
+                    caster = Some((
+                        Vec3n::new(direction.x, direction.y, direction.z).normalized(),
+                        shadow,
+                    ));
+                }
+            }
+        });
+
+        self.cascades.clear();
+        self.settings = None;
+        let (light_direction, shadow) = match caster {
+            Some(caster) => caster,
+            None => return,
+        };
+
+        let cascade_count = shadow.cascade_count.max(1).min(MAX_CASCADES);
+        let resolution = shadow.resolution.max(1);
+        let outdated = match &self.map {
+            Some(map) => map.resolution != resolution || map.layers != cascade_count as u32,
+            None => true,
+        };
+        if outdated {
+            self.map = Some(CascadeMap::new(display, resolution, cascade_count as u32));
+        }
+
+        let (near, far) = get_shadow_range(projection, &shadow);
+        let camera_to_world = view_matrix.inverse();
+        let up = if light_direction.y.abs() > 0.99 {
+            Vec3n::forward()
+        } else {
+            Vec3n::up()
+        };
+        let light_rotation = get_view_matrix(&Vec3n::zero(), &light_direction, &up);
+        let light_rotation_inverse = light_rotation.inverse();
+
+        let mut start = near;
+        for split in get_split_distances(near, far, cascade_count, shadow.split_lambda) {
+            let corners: Vec<Vec3n> =
+                get_frustum_corners(projection, viewport.x / viewport.y, start, split)
+                    .into_iter()
+                    .map(|corner| transform_point(&camera_to_world, corner))
+                    .collect();
+            let mut center = Vec3n::zero();
+            for corner in corners.iter() {
+                center += *corner;
+            }
+            center = center * (1.0 / corners.len() as f32);
+
+            //Fitting a sphere instead of a box keeps the cascade's size the same as the camera turns
+            let radius = corners
+                .iter()
+                .map(|corner| (*corner - center).length())
+                .fold(0.0f32, |a, b| a.max(b));
+
+            //Moving the cascade in whole texels keeps shadow edges from shimmering as the camera moves
+            let texel_size = 2.0 * radius / resolution as f32;
+            let light_center = transform_point(&light_rotation, center);
+            let snapped = Vec3n::new(
+                (light_center.x / texel_size).floor() * texel_size,
+                (light_center.y / texel_size).floor() * texel_size,
+                light_center.z,
+            );
+            let center = transform_point(&light_rotation_inverse, snapped);
+
+            let eye = center - light_direction * (radius + shadow.caster_distance);
+            let light_view = get_view_matrix(&eye, &light_direction, &up);
+            let light_projection = Projection::Orthographic {
+                size: radius,
+                near: 0.0,
+                far: 2.0 * radius + shadow.caster_distance,
+            }
+            .get_matrix(1.0, false);
+
+            self.cascades.push(Cascade {
+                light_view_projection: light_projection * light_view,
+                split,
+            });
+            start = split;
+        }
+        self.settings = Some(shadow);
+
+        let map = self.map.as_ref().unwrap();
+        for (index, cascade) in self.cascades.iter().enumerate() {
+            let mut surface = SimpleFrameBuffer::with_depth_buffer(
+                display,
+                map.depths.main_level().layer(index as u32).unwrap(),
+                &map.depth,
+            )
+            .unwrap();
+            surface.clear_color_and_depth((1.0, 1.0, 1.0, 1.0), 1.0);
+            draw_scene(
+                &mut surface,
+                &CascadeShadowPass {
+                    program: &self.program,
+                    draw_parameters: &self.draw_parameters,
+                    light_view_projection: cascade.light_view_projection,
+                    cascade: index,
+                },
+            );
+        }
+    }
+
+    //Adds the cascades to the uniforms a lit shader is drawn with, the view matrix has to be the one of the camera
+    //the cascades were rendered for
+    pub fn get_uniforms<'a, U: Uniforms>(
+        &'a self,
+        view_matrix: &Mat4n,
+        uniforms: &'a U,
+    ) -> CascadedShadowUniforms<'a, U> {
+        let view_to_world = view_matrix.inverse();
+        let mut splits = [0.0; MAX_CASCADES];
+        let mut matrices = [Mat4n::identity(); MAX_CASCADES];
+        for (index, cascade) in self.cascades.iter().enumerate() {
+            splits[index] = cascade.split;
+            matrices[index] = cascade.light_view_projection * view_to_world;
+        }
+        CascadedShadowUniforms {
+            shadow_maps: self,
+            matrices,
+            splits,
+            uniforms,
+        }
+    }
+}
+
+pub struct CascadedShadowUniforms<'a, U: Uniforms> {
+    shadow_maps: &'a CascadedShadowMaps,
+    matrices: [Mat4n; MAX_CASCADES],
+    splits: [f32; MAX_CASCADES],
+    uniforms: &'a U,
+}
+
+impl<'a, U: Uniforms> Uniforms for CascadedShadowUniforms<'a, U> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut output: F) {
+        self.uniforms.visit_values(&mut output);
+
+        let shadow_maps = self.shadow_maps;
+        let (bias, pcf_radius, blend) = match &shadow_maps.settings {
+            Some(settings) => (settings.bias, settings.pcf_radius, settings.blend),
+            None => (0.0, 0, 0.0),
+        };
+        let texture = match &shadow_maps.map {
+            Some(map) if shadow_maps.settings.is_some() => &map.depths,
+            _ => &shadow_maps.empty_map,
+        };
+        let sampler = SamplerBehavior {
+            wrap_function: (
+                SamplerWrapFunction::Clamp,
+                SamplerWrapFunction::Clamp,
+                SamplerWrapFunction::Clamp,
+            ),
+            minify_filter: MinifySamplerFilter::Nearest,
+            magnify_filter: MagnifySamplerFilter::Nearest,
+            ..Default::default()
+        };
+
+        output(
+            "cascade_shadow_map",
+            UniformValue::Texture2dArray(texture, Some(sampler)),
+        );
+        for (name, matrix) in CASCADE_MATRIX_NAMES.iter().zip(self.matrices.iter()) {
+            output(name, matrix.as_uniform_value());
+        }
+        output("cascade_splits", UniformValue::Vec4(self.splits));
+        output(
+            "cascade_count",
+            UniformValue::SignedInt(shadow_maps.cascades.len() as i32),
+        );
+        output("cascade_blend", UniformValue::Float(blend));
+        output("cascade_bias", UniformValue::Float(bias));
+        output(
+            "cascade_pcf_radius",
+            UniformValue::SignedInt(pcf_radius as i32),
+        );
+        output("cascade_debug", UniformValue::Bool(shadow_maps.debug_view));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEAR: f32 = 0.5;
+    const FAR: f32 = 100.0;
+    const ASPECT_RATIO: f32 = 1.5;
+
+    fn assert_near(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} is not close to {}", a, b);
+    }
+
+    fn perspective(far: Option<f32>) -> Projection {
+        Projection::Perspective {
+            fov: 1.0,
+            near: NEAR,
+            far,
+        }
+    }
+
+    #[test]
+    fn splits_grow_towards_far() {
+        for lambda in [0.0, 0.5, 0.75, 1.0].iter() {
+            let splits = get_split_distances(NEAR, FAR, 4, *lambda);
+            assert_eq!(splits.len(), 4);
+            assert!(splits[0] > NEAR);
+            assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_near(splits[3], FAR);
+        }
+        assert_eq!(get_split_distances(NEAR, FAR, 1, 0.75).len(), 1);
+    }
+
+    #[test]
+    fn split_lambda_blends_even_and_logarithmic_splits() {
+        let even = get_split_distances(NEAR, FAR, 4, 0.0);
+        for (i, split) in even.iter().enumerate() {
+            assert_near(*split, NEAR + (FAR - NEAR) * (i + 1) as f32 / 4.0);
+        }
+
+        //Every cascade is the same factor deeper than the one before
+        let logarithmic = get_split_distances(NEAR, FAR, 4, 1.0);
+        let factor = (FAR / NEAR).powf(0.25);
+        assert_near(logarithmic[0], NEAR * factor);
+        for pair in logarithmic.windows(2) {
+            assert_near(pair[1] / pair[0], factor);
+        }
+
+        let blended = get_split_distances(NEAR, FAR, 4, 0.5);
+        for i in 0..4 {
+            assert_near(blended[i], (even[i] + logarithmic[i]) / 2.0);
+        }
+    }
+
+    #[test]
+    fn shadows_end_at_max_distance_or_the_far_plane() {
+        let shadow = DirectionalShadow {
+            max_distance: 50.0,
+            ..DirectionalShadow::default()
+        };
+        assert_eq!(
+            get_shadow_range(&perspective(Some(FAR)), &shadow),
+            (NEAR, 50.0)
+        );
+        assert_eq!(get_shadow_range(&perspective(None), &shadow), (NEAR, 50.0));
+        assert_eq!(
+            get_shadow_range(&perspective(Some(20.0)), &shadow),
+            (NEAR, 20.0)
+        );
+        let orthographic = Projection::Orthographic {
+            size: 10.0,
+            near: 1.0,
+            far: 30.0,
+        };
+        assert_eq!(get_shadow_range(&orthographic, &shadow), (1.0, 30.0));
+    }
+
+    #[test]
+    fn frustum_corners_land_on_the_edges_of_the_view() {
+        let orthographic = Projection::Orthographic {
+            size: 10.0,
+            near: NEAR,
+            far: FAR,
+        };
+        for projection in [perspective(Some(FAR)), orthographic].iter() {
+            let matrix = projection.get_matrix(ASPECT_RATIO, false);
+            let corners = get_frustum_corners(projection, ASPECT_RATIO, NEAR, FAR);
+            assert_eq!(corners.len(), 8);
+            for (i, corner) in corners.iter().enumerate() {
+                //The first four are on the start plane, which is the near plane here
+                let depth = if i < 4 { -1.0 } else { 1.0 };
+                let clip = matrix * Vec4n::from((*corner, 1.0));
+                assert_near(clip.x.abs() / clip.w, 1.0);
+                assert_near(clip.y.abs() / clip.w, 1.0);
+                assert_near(clip.z / clip.w, depth);
+            }
+        }
+    }
+
+    #[test]
+    fn frustum_corners_cover_the_requested_distances() {
+        let corners = get_frustum_corners(&perspective(Some(FAR)), ASPECT_RATIO, 2.0, 8.0);
+        for (i, corner) in corners.iter().enumerate() {
+            let distance = if i < 4 { 2.0 } else { 8.0 };
+            assert_eq!(corner.z, distance);
+            assert_near(corner.y.abs(), (0.5f32).tan() * distance);
+            assert_near(corner.x.abs(), (0.5f32).tan() * distance * ASPECT_RATIO);
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod cascaded_shadows;
+pub mod point_shadows;
@@ -0,0 +1,293 @@
+use glium::framebuffer::{DepthRenderBuffer, SimpleFrameBuffer};
+use glium::texture::{CubeLayer, Cubemap, DepthFormat, MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::{
+    AsUniformValue, MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior,
+    SamplerWrapFunction, UniformValue, Uniforms,
+};
+use glium::Surface;
+use straal::{Mat4n, Vec3n, Vec4n};
+
+use crate::renderer::matrix_helpers::get_view_matrix;
+use crate::renderer::projection::Projection;
+use crate::renderer::scene_map::components::{Light, LightKind, PointShadow};
+use crate::renderer::scene_map::node_handle::NodeHandle;
+use crate::renderer::scene_map::scene_container::SceneContainer;
+use crate::renderer::shader_helpers::{Shader, ShaderError};
+
+//Has to match MAX_POINT_SHADOWS in res/shaders/common/point_shadows.glsl
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+const POINT_SHADOW_MAP_NAMES: [&str; MAX_POINT_SHADOWS] = [
+    "point_shadow_map_0",
+    "point_shadow_map_1",
+    "point_shadow_map_2",
+    "point_shadow_map_3",
+];
+
+//Direction and up vector of every cube face, following OpenGL's cube map layout
+const CUBE_FACES: [(CubeLayer, [f32; 3], [f32; 3]); 6] = [
+    (CubeLayer::PositiveX, [1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    (CubeLayer::NegativeX, [-1.0, 0.0, 0.0], [0.0, -1.0, 0.0]),
+    (CubeLayer::PositiveY, [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+    (CubeLayer::NegativeY, [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]),
+    (CubeLayer::PositiveZ, [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]),
+    (CubeLayer::NegativeZ, [0.0, 0.0, -1.0], [0.0, -1.0, 0.0]),
+];
+
+//The shadow settings of a light if it casts point shadows, shared with LightBlock::gather so both agree on shadow slots.
+//Slots are handed out in pre-order, lights past MAX_POINT_SHADOWS do not cast shadows.
+pub(crate) fn get_point_shadow(
+    container: &SceneContainer,
+    node_id: NodeHandle,
+    light: &Light,
+) -> Option<PointShadow> {
+    match light.kind {
+        LightKind::Point(_) => container.get_component::<PointShadow>(node_id).cloned(),
+        _ => None,
+    }
+}
+
+//World position and settings of every light that gets a shadow map, the index is its slot
+pub(crate) fn get_point_shadow_casters(
+    container: &SceneContainer,
+) -> Vec<(NodeHandle, Vec3n, PointShadow)> {
+    let mut casters = Vec::new();
+    container.visit_with_world(|node_id, _, node, parent_world| {
+        if casters.len() >= MAX_POINT_SHADOWS {
+            return;
+        }
+        if let Some(light) = container.get_component::<Light>(node_id) {
+            if let Some(shadow) = get_point_shadow(container, node_id, light) {
+                let world = *parent_world * node.get_transform().get_local_matrix();
+                let position = world * Vec4n::new(0.0, 0.0, 0.0, 1.0);
+                casters.push((
+                    node_id,
+                    Vec3n::new(position.x, position.y, position.z),
+                    shadow,
+                ));
+            }
+        }
+    });
+    casters
+}
+
+struct PointShadowMap {
+    distances: Cubemap,
+    depth: DepthRenderBuffer,
+    resolution: u32,
+}
+
+impl PointShadowMap {
+    fn new(display: &glium::Display, resolution: u32) -> PointShadowMap {
+        PointShadowMap {
+            distances: Cubemap::empty_with_format(
+                display,
+                UncompressedFloatFormat::F32,
+                MipmapsOption::NoMipmap,
+                resolution,
+            )
+            .unwrap(),
+            depth: DepthRenderBuffer::new(display, DepthFormat::I24, resolution, resolution)
+                .unwrap(),
+            resolution,
+        }
+    }
+}
+
+//Everything a draw call of the shadow pass needs, handed to the closure that draws the scene
+pub struct PointShadowPass<'a> {
+    program: &'a glium::Program,
+    draw_parameters: &'a glium::DrawParameters<'static>,
+    light_position: Vec3n,
+    light_view_projection: Mat4n,
+    far: f32,
+}
+
+impl<'a> PointShadowPass<'a> {
+    pub fn get_program(&self) -> &glium::Program {
+        self.program
+    }
+
+    pub fn get_draw_parameters(&self) -> &glium::DrawParameters<'static> {
+        self.draw_parameters
+    }
+
+    pub fn get_uniforms(&self, model_matrix: Mat4n) -> PointShadowPassUniforms {
+        PointShadowPassUniforms {
+            model: model_matrix,
+            light_view_projection: self.light_view_projection,
+            light_position: self.light_position,
+            far: self.far,
+        }
+    }
+}
+
+pub struct PointShadowPassUniforms {
+    model: Mat4n,
+    light_view_projection: Mat4n,
+    light_position: Vec3n,
+    far: f32,
+}
+
+impl Uniforms for PointShadowPassUniforms {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut output: F) {
+        output("model", self.model.as_uniform_value());
+        output(
+            "light_view_projection",
+            self.light_view_projection.as_uniform_value(),
+        );
+        output("light_position", self.light_position.as_uniform_value());
+        output("far", UniformValue::Float(self.far));
+    }
+}
+
+//Cube maps holding the distance from every shadow casting point light to the nearest surface around it
+pub struct PointShadowMaps {
+    program: glium::Program,
+    draw_parameters: glium::DrawParameters<'static>,
+    maps: Vec<PointShadowMap>,
+    //Bound to unused slots, it is as far away as it gets so nothing is shadowed by it
+    empty_map: Cubemap,
+}
+
+impl PointShadowMaps {
+    pub fn new(display: &glium::Display) -> Result<PointShadowMaps, ShaderError> {
+        let empty_map = Cubemap::empty_with_format(
+            display,
+            UncompressedFloatFormat::F32,
+            MipmapsOption::NoMipmap,
+            1,
+        )
+        .unwrap();
+        for (layer, _, _) in CUBE_FACES.iter() {
+            SimpleFrameBuffer::new(display, empty_map.main_level().image(*layer))
+                .unwrap()
+                .clear_color(1.0, 1.0, 1.0, 1.0);
+        }
+
+        Ok(PointShadowMaps {
+            program: Shader::load(display, Shader::POINT_SHADOW)?,
+            draw_parameters: glium::DrawParameters {
+                depth: glium::Depth {
+                    test: glium::draw_parameters::DepthTest::IfLess,
+                    write: true,
+                    ..Default::default()
+                },
+                //The faces are mirrored to match the cube map layout, which flips the winding
+                backface_culling: glium::draw_parameters::BackfaceCullingMode::CullingDisabled,
+                ..Default::default()
+            },
+            maps: Vec::new(),
+            empty_map,
+        })
+    }
+
+    //Renders all six faces of every shadow casting point light.
+    //The closure draws every shadow casting mesh into the given surface, with the pass's program, draw parameters
+    //and the uniforms it gives for the mesh's model matrix.
+    pub fn render<F>(
+        &mut self,
+        display: &glium::Display,
+        container: &SceneContainer,
+        mut draw_scene: F,
+    ) where
+        F: FnMut(&mut SimpleFrameBuffer, &PointShadowPass),
+    {
+        let casters = get_point_shadow_casters(container);
+        self.maps.truncate(casters.len());
+        for (index, (_, position, shadow)) in casters.into_iter().enumerate() {
+            let resolution = shadow.resolution.max(1);
+            if index == self.maps.len() {
+                self.maps.push(PointShadowMap::new(display, resolution));
+            } else if self.maps[index].resolution != resolution {
+                self.maps[index] = PointShadowMap::new(display, resolution);
+            }
+            let map = &self.maps[index];
+
+            //Mirrors x so the left handed view matrices line up with the cube map layout
+            #[rustfmt::skip]
+            let mirror = Mat4n::new(
+                -1.0, 0.0, 0.0, 0.0,
+                0.0, 1.0, 0.0, 0.0,
+                0.0, 0.0, 1.0, 0.0,
+                0.0, 0.0, 0.0, 1.0,
+            );
+            let projection = mirror
+                * Projection::Perspective {
+                    fov: std::f32::consts::FRAC_PI_2,
+                    near: shadow.near,
+                    far: Some(shadow.far),
+                }
+                .get_matrix(1.0, false);
+
+            for (layer, direction, up) in CUBE_FACES.iter() {
+                let view = get_view_matrix(
+                    &position,
+                    &Vec3n::new(direction[0], direction[1], direction[2]),
+                    &Vec3n::new(up[0], up[1], up[2]),
+                );
+                let mut surface = SimpleFrameBuffer::with_depth_buffer(
+                    display,
+                    map.distances.main_level().image(*layer),
+                    &map.depth,
+                )
+                .unwrap();
+                surface.clear_color_and_depth((1.0, 1.0, 1.0, 1.0), 1.0);
+                draw_scene(
+                    &mut surface,
+                    &PointShadowPass {
+                        program: &self.program,
+                        draw_parameters: &self.draw_parameters,
+                        light_position: position,
+                        light_view_projection: projection * view,
+                        far: shadow.far,
+                    },
+                );
+            }
+        }
+    }
+
+    //Adds the shadow maps to the uniforms a lit shader is drawn with
+    pub fn get_uniforms<'a, U: Uniforms>(
+        &'a self,
+        view_matrix: &Mat4n,
+        uniforms: &'a U,
+    ) -> PointShadowUniforms<'a, U> {
+        PointShadowUniforms {
+            shadow_maps: self,
+            view_to_world: view_matrix.inverse(),
+            uniforms,
+        }
+    }
+}
+
+pub struct PointShadowUniforms<'a, U: Uniforms> {
+    shadow_maps: &'a PointShadowMaps,
+    view_to_world: Mat4n,
+    uniforms: &'a U,
+}
+
+impl<'a, U: Uniforms> Uniforms for PointShadowUniforms<'a, U> {
+    fn visit_values<'b, F: FnMut(&str, UniformValue<'b>)>(&'b self, mut output: F) {
+        self.uniforms.visit_values(&mut output);
+        output("view_to_world", self.view_to_world.as_uniform_value());
+
+        let sampler = SamplerBehavior {
+            wrap_function: (
+                SamplerWrapFunction::Clamp,
+                SamplerWrapFunction::Clamp,
+                SamplerWrapFunction::Clamp,
+            ),
+            minify_filter: MinifySamplerFilter::Nearest,
+            magnify_filter: MagnifySamplerFilter::Nearest,
+            ..Default::default()
+        };
+        for (slot, name) in POINT_SHADOW_MAP_NAMES.iter().enumerate() {
+            let cubemap = match self.shadow_maps.maps.get(slot) {
+                Some(map) => &map.distances,
+                None => &self.shadow_maps.empty_map,
+            };
+            output(name, UniformValue::Cubemap(cubemap, Some(sampler)));
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use glium::texture::{MipmapsOption, RawImage2d, SrgbTexture2d, Texture2d, TextureCreationError};
+use glium::uniforms::{
+    MagnifySamplerFilter, MinifySamplerFilter, SamplerBehavior, SamplerWrapFunction, UniformValue,
+};
+
+const CHECKERBOARD_SIZE: u32 = 64;
+const CHECKERBOARD_CELL: u32 = 8;
+
+//Colour data (diffuse maps) is stored in sRGB, data textures (normal maps, roughness...) have to stay linear
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureSettings {
+    pub color_space: ColorSpace,
+    pub mipmaps: bool,
+}
+
+impl TextureSettings {
+    pub const COLOR: TextureSettings = TextureSettings {
+        color_space: ColorSpace::Srgb,
+        mipmaps: true,
+    };
+    pub const DATA: TextureSettings = TextureSettings {
+        color_space: ColorSpace::Linear,
+        mipmaps: true,
+    };
+}
+
+//How a texture is sampled, this belongs to the place a texture is bound rather than the texture itself
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerSettings {
+    pub wrap: SamplerWrapFunction,
+    pub minify: MinifySamplerFilter,
+    pub magnify: MagnifySamplerFilter,
+    pub anisotropy: u16,
+}
+
+impl Default for SamplerSettings {
+    fn default() -> SamplerSettings {
+        SamplerSettings {
+            wrap: SamplerWrapFunction::Repeat,
+            minify: MinifySamplerFilter::LinearMipmapLinear,
+            magnify: MagnifySamplerFilter::Linear,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl SamplerSettings {
+    pub fn get_sampler_behavior(&self) -> SamplerBehavior {
+        SamplerBehavior {
+            wrap_function: (self.wrap, self.wrap, self.wrap),
+            minify_filter: self.minify,
+            magnify_filter: self.magnify,
+            max_anisotropy: self.anisotropy.max(1),
+            ..Default::default()
+        }
+    }
+}
+
+pub enum Texture {
+    Linear(Texture2d),
+    Srgb(SrgbTexture2d),
+}
+
+impl Texture {
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        match self {
+            Texture::Linear(texture) => texture.dimensions(),
+            Texture::Srgb(texture) => texture.dimensions(),
+        }
+    }
+
+    pub fn get_color_space(&self) -> ColorSpace {
+        match self {
+            Texture::Linear(_) => ColorSpace::Linear,
+            Texture::Srgb(_) => ColorSpace::Srgb,
+        }
+    }
+
+    pub fn as_uniform_value(&self, sampler: Option<SamplerBehavior>) -> UniformValue {
+        match self {
+            Texture::Linear(texture) => UniformValue::Texture2d(texture, sampler),
+            Texture::Srgb(texture) => UniformValue::SrgbTexture2d(texture, sampler),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Decode(PathBuf, image::ImageError),
+    Creation(PathBuf, TextureCreationError),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Decode(path, error) => {
+                write!(f, "Could not load {}: {}", path.display(), error)
+            }
+            TextureError::Creation(path, error) => {
+                write!(
+                    f,
+                    "Could not upload {} to the GPU: {:?}",
+                    path.display(),
+                    error
+                )
+            }
+        }
+    }
+}
+
+impl Error for TextureError {}
+
+//Loads images (PNG, JPEG, TGA...) from disk into textures, every path is only loaded once per set of settings.
+//A texture that fails to load is replaced by a checkerboard so the problem is visible instead of fatal.
+pub struct TextureManager {
+    textures: HashMap<(PathBuf, TextureSettings), Rc<Texture>>,
+    errors: HashMap<PathBuf, TextureError>,
+    //Paths and settings that showed the fallback, they are not read again until reload is called
+    failed: HashSet<(PathBuf, TextureSettings)>,
+    fallback: Option<Rc<Texture>>,
+}
+
+impl Default for TextureManager {
+    fn default() -> TextureManager {
+        TextureManager::new()
+    }
+}
+
+impl TextureManager {
+    pub fn new() -> TextureManager {
+        TextureManager {
+            textures: HashMap::new(),
+            errors: HashMap::new(),
+            failed: HashSet::new(),
+            fallback: None,
+        }
+    }
+
+    //Always gives back a texture, falling back to the checkerboard when the file could not be loaded.
+    //The reason it failed is kept and can be reported by the caller through get_error.
+    //A failed load keeps giving the checkerboard without touching the file again, until reload is called.
+    pub fn load<P: AsRef<Path>>(
+        &mut self,
+        display: &glium::Display,
+        path: P,
+        settings: TextureSettings,
+    ) -> Rc<Texture> {
+        let key = (path.as_ref().to_path_buf(), settings);
+        if self.failed.contains(&key) {
+            return self.get_fallback(display);
+        }
+        match self.try_load(display, &key.0, settings) {
+            Ok(texture) => texture,
+            Err(error) => {
+                self.errors.insert(key.0.clone(), error);
+                self.failed.insert(key);
+                self.get_fallback(display)
+            }
+        }
+    }
+
+    //Forgets the texture loaded from a path with the given settings, or that it failed to load, and loads it again.
+    //Meant for when the file changed on disk.
+    pub fn reload<P: AsRef<Path>>(
+        &mut self,
+        display: &glium::Display,
+        path: P,
+        settings: TextureSettings,
+    ) -> Rc<Texture> {
+        let key = (path.as_ref().to_path_buf(), settings);
+        self.textures.remove(&key);
+        self.failed.remove(&key);
+        self.errors.remove(&key.0);
+        self.load(display, &key.0, settings)
+    }
+
+    //Unlike load this tries the file again after an earlier failure
+    pub fn try_load<P: AsRef<Path>>(
+        &mut self,
+        display: &glium::Display,
+        path: P,
+        settings: TextureSettings,
+    ) -> Result<Rc<Texture>, TextureError> {
+        let key = (path.as_ref().to_path_buf(), settings);
+        if let Some(texture) = self.textures.get(&key) {
+            return Ok(texture.clone());
+        }
+
+        let texture = Rc::new(TextureManager::read_texture(display, &key.0, settings)?);
+        self.errors.remove(&key.0);
+        self.failed.remove(&key);
+        self.textures.insert(key, texture.clone());
+        Ok(texture)
+    }
+
+    fn read_texture(
+        display: &glium::Display,
+        path: &Path,
+        settings: TextureSettings,
+    ) -> Result<Texture, TextureError> {
+        let image = image::open(path)
+            .map_err(|e| TextureError::Decode(path.to_path_buf(), e))?
+            .to_rgba();
+        let dimensions = image.dimensions();
+        let raw = RawImage2d::from_raw_rgba_reversed(&image.into_raw(), dimensions);
+        TextureManager::create_texture(display, raw, settings)
+            .map_err(|e| TextureError::Creation(path.to_path_buf(), e))
+    }
+
+    fn create_texture(
+        display: &glium::Display,
+        raw: RawImage2d<u8>,
+        settings: TextureSettings,
+    ) -> Result<Texture, TextureCreationError> {
+        let mipmaps = if settings.mipmaps {
+            MipmapsOption::AutoGeneratedMipmaps
+        } else {
+            MipmapsOption::NoMipmap
+        };
+        Ok(match settings.color_space {
+            ColorSpace::Linear => Texture::Linear(Texture2d::with_mipmaps(display, raw, mipmaps)?),
+            ColorSpace::Srgb => Texture::Srgb(SrgbTexture2d::with_mipmaps(display, raw, mipmaps)?),
+        })
+    }
+
+    //Magenta and black checkerboard, created the first time it is needed
+    pub fn get_fallback(&mut self, display: &glium::Display) -> Rc<Texture> {
+        if let Some(fallback) = &self.fallback {
+            return fallback.clone();
+        }
+
+        let mut pixels = Vec::with_capacity((CHECKERBOARD_SIZE * CHECKERBOARD_SIZE * 4) as usize);
+        for y in 0..CHECKERBOARD_SIZE {
+            for x in 0..CHECKERBOARD_SIZE {
+                let lit = (x / CHECKERBOARD_CELL + y / CHECKERBOARD_CELL) % 2 == 0;
+                pixels.extend_from_slice(if lit {
+                    &[255, 0, 255, 255]
+                } else {
+                    &[0, 0, 0, 255]
+                });
+            }
+        }
+        let raw = RawImage2d::from_raw_rgba(pixels, (CHECKERBOARD_SIZE, CHECKERBOARD_SIZE));
+        let settings = TextureSettings {
+            color_space: ColorSpace::Linear,
+            mipmaps: false,
+        };
+        let fallback = Rc::new(
+            TextureManager::create_texture(display, raw, settings)
+                .expect("The fallback texture could not be created"),
+        );
+        self.fallback = Some(fallback.clone());
+        fallback
+    }
+
+    //The reason a path is currently showing the fallback texture, if any
+    pub fn get_error<P: AsRef<Path>>(&self, path: P) -> Option<&TextureError> {
+        self.errors.get(path.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.textures.clear();
+        self.errors.clear();
+        self.failed.clear();
+    }
+}
@@ -2,14 +2,21 @@ use std::fmt;
 
 use straal::{FloatType, Mat2, Mat3, Mat4, Quat, RotationOrder, Vec2, Vec3, Vec4};
 
-use crate::get_model_matrix;
-
 type RelatedTransform<S> = Rc<Cell<Transform<S>>>;
 
 use super::*;
 use core::borrow::Borrow;
 use std::cell::Cell;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+//Revisions are handed out from a single counter so no two transforms ever share one,
+//this lets a child tell whether the parent space it cached is still the current one
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
+}
 
 pub enum Space {
     Local,
@@ -29,6 +36,13 @@ pub struct Transform<S> {
     scale: Vec3<S>,
     transform: Mat4<S>,
     changed: bool,
+    parent_transform: Mat4<S>,
+    parent_rotation: Quat<S>,
+    parent_scale: Vec3<S>,
+    parent_revision: Option<u64>,
+    world_transform: Mat4<S>,
+    world_changed: bool,
+    revision: u64,
 }
 
 #[allow(dead_code)]
@@ -44,24 +58,86 @@ where
             scale: Vec3::one(),
             transform: Mat4::identity(),
             changed: true,
+            parent_transform: Mat4::identity(),
+            parent_rotation: Quat::identity(),
+            parent_scale: Vec3::one(),
+            parent_revision: None,
+            world_transform: Mat4::identity(),
+            world_changed: true,
+            revision: next_revision(),
         }
     }
 
+    fn mark_changed(&mut self) {
+        self.changed = true;
+        self.world_changed = true;
+        self.revision = next_revision();
+    }
+
     fn update_matrix(&mut self) {
-        let s = Mat4::get_uniform_scale_mat(self.scale);
-        let r = Mat4::from(self.local_rotation);
         let t = Mat4::get_translation_mat(self.position);
-        self.transform = s * r * t;
+        let r = Mat4::from(self.local_rotation);
+        let s = Mat4::get_uniform_scale_mat(self.scale);
+        self.transform = t * r * s;
         self.changed = false;
     }
 
-    pub fn get_local_to_world_matrix(&mut self) -> Mat4<S> {
+    fn update_world_matrix(&mut self) {
+        if self.changed {
+            self.update_matrix();
+        }
+        self.world_transform = self.parent_transform * self.transform;
+        self.world_rotation = self.parent_rotation * self.local_rotation;
+        self.world_changed = false;
+    }
+
+    //Changes every time the world space result of this transform changes,
+    //either through its own local values or through the parent space it sits in
+    pub fn get_revision(&self) -> u64 {
+        self.revision
+    }
+
+    //Places this transform in the world space of the given parent,
+    //nothing is invalidated when the parent has not changed since the last call
+    pub fn set_parent(&mut self, parent: &mut Transform<S>) {
+        if self.parent_revision == Some(parent.get_revision()) {
+            return;
+        }
+        self.parent_transform = parent.get_local_to_world_matrix();
+        self.parent_rotation = parent.get_world_rotation();
+        self.parent_scale = parent.get_world_scale();
+        self.parent_revision = Some(parent.get_revision());
+        self.world_changed = true;
+        self.revision = next_revision();
+    }
+
+    //Turns this transform back into a root, making local space equal to world space
+    pub fn clear_parent(&mut self) {
+        if self.parent_revision.is_none() {
+            return;
+        }
+        self.parent_transform = Mat4::identity();
+        self.parent_rotation = Quat::identity();
+        self.parent_scale = Vec3::one();
+        self.parent_revision = None;
+        self.world_changed = true;
+        self.revision = next_revision();
+    }
+
+    pub fn get_local_matrix(&mut self) -> Mat4<S> {
         if self.changed {
             self.update_matrix();
         }
         self.transform
     }
 
+    pub fn get_local_to_world_matrix(&mut self) -> Mat4<S> {
+        if self.changed || self.world_changed {
+            self.update_world_matrix();
+        }
+        self.world_transform
+    }
+
     pub fn get_world_to_local_matrix(&mut self) -> Mat4<S> {
         self.get_local_to_world_matrix().inverse()
     }
@@ -72,11 +148,18 @@ where
 
     pub fn set_local_position(&mut self, position: Vec3<S>) {
         self.position = position;
-        self.changed = true;
+        self.mark_changed();
     }
 
-    pub fn get_world_position() -> Vec3<S> {
-        unimplemented!()
+    pub fn get_world_position(&self) -> Vec3<S> {
+        Vec3::from(self.parent_transform * Vec4::from((self.position, S::one())))
+    }
+
+    //Sets the local position so the transform ends up at the given position in its current parent space
+    pub fn set_world_position(&mut self, position: Vec3<S>) {
+        let local = self.parent_transform.inverse() * Vec4::from((position, S::one()));
+        self.position = Vec3::from(local);
+        self.mark_changed();
     }
 
     pub fn get_local_rotation(&self) -> Quat<S> {
@@ -85,11 +168,19 @@ where
 
     pub fn set_local_rotation(&mut self, rotation: Quat<S>) {
         self.local_rotation = rotation;
-        self.changed = true;
+        self.mark_changed();
     }
 
-    pub fn get_world_rotation() -> Quat<S> {
-        unimplemented!()
+    pub fn get_world_rotation(&mut self) -> Quat<S> {
+        if self.changed || self.world_changed {
+            self.update_world_matrix();
+        }
+        self.world_rotation
+    }
+
+    pub fn set_world_rotation(&mut self, rotation: Quat<S>) {
+        self.local_rotation = self.parent_rotation.inverse() * rotation;
+        self.mark_changed();
     }
 
     pub fn get_local_scale(&self) -> Vec3<S> {
@@ -98,16 +189,38 @@ where
 
     pub fn set_local_scale(&mut self, scale: Vec3<S>) {
         self.scale = scale;
-        self.changed = true;
+        self.mark_changed();
     }
 
-    pub fn get_world_scale() -> Vec3<S> {
-        unimplemented!()
+    //Like most engines this is a lossy scale, skew introduced by rotated parents is ignored
+    pub fn get_world_scale(&self) -> Vec3<S> {
+        Vec3::new(
+            self.parent_scale.x * self.scale.x,
+            self.parent_scale.y * self.scale.y,
+            self.parent_scale.z * self.scale.z,
+        )
+    }
+
+    //An axis the parent flattened to zero can not be scaled back out, it keeps its current local value
+    pub fn set_world_scale(&mut self, scale: Vec3<S>) {
+        let divide = |world: S, parent: S, local: S| {
+            if parent == S::zero() {
+                local
+            } else {
+                world / parent
+            }
+        };
+        self.scale = Vec3::new(
+            divide(scale.x, self.parent_scale.x, self.scale.x),
+            divide(scale.y, self.parent_scale.y, self.scale.y),
+            divide(scale.z, self.parent_scale.z, self.scale.z),
+        );
+        self.mark_changed();
     }
 
     pub fn translate(&mut self, delta_pos: Vec3<S>) {
         self.position += delta_pos;
-        self.changed = true;
+        self.mark_changed();
     }
 
     pub fn get_right(&mut self) -> Vec3<S> {
@@ -139,7 +252,7 @@ where
         let rht = up.cross(fwd).normalized();
         let up = fwd.cross(rht).normalized();
         self.local_rotation = Quat::from(Mat3::new_from_vec3s(rht, up, fwd));
-        self.changed = true;
+        self.mark_changed();
     }
     //
     //    pub fn rotate_around(&mut self, point: Vec3<S>, axis: Vec3<S>, theta: S) {
@@ -216,3 +329,41 @@ where
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use straal::{Quatn, Vec3n, Vec4n};
+
+    #[test]
+    fn local_matrix_scales_then_rotates_then_translates() {
+        let mut transform = Transform::<f32>::default();
+        transform.set_local_position(Vec3n::new(1.0, 2.0, 3.0));
+        transform.set_local_rotation(Quatn::get_quat_from_angle_axis(
+            std::f32::consts::PI,
+            Vec3n::up(),
+        ));
+        transform.set_local_scale(Vec3n::all(2.0));
+
+        //(1,0,0) scaled to (2,0,0), turned to (-2,0,0), moved to (-1,2,3)
+        let point = transform.get_local_matrix() * Vec4n::new(1.0, 0.0, 0.0, 1.0);
+        assert!((point.x + 1.0).abs() < 1e-4, "{:?}", point);
+        assert!((point.y - 2.0).abs() < 1e-4, "{:?}", point);
+        assert!((point.z - 3.0).abs() < 1e-4, "{:?}", point);
+    }
+
+    #[test]
+    fn world_scale_keeps_axes_a_flat_parent_can_not_undo() {
+        let mut parent = Transform::<f32>::default();
+        parent.set_local_scale(Vec3n::new(2.0, 0.0, 4.0));
+        let mut child = Transform::<f32>::default();
+        child.set_local_scale(Vec3n::new(1.0, 3.0, 1.0));
+        child.set_parent(&mut parent);
+
+        child.set_world_scale(Vec3n::all(1.0));
+        let scale = child.get_local_scale();
+        assert_eq!((scale.x, scale.y, scale.z), (0.5, 3.0, 0.25));
+        let world = child.get_world_scale();
+        assert_eq!((world.x, world.y, world.z), (1.0, 0.0, 1.0));
+    }
+}